@@ -0,0 +1,8 @@
+#!/bin/bash
+# Create the DataStore database and its read-only role on the first start of the container
+set -e
+
+psql -v ON_ERROR_STOP=1 --username "$POSTGRES_USER" --dbname "$POSTGRES_DB" <<-EOSQL
+    CREATE ROLE datastore_default NOSUPERUSER NOCREATEDB NOCREATEROLE LOGIN PASSWORD '$DATASTORE_READONLY_PASSWORD';
+    CREATE DATABASE datastore_default OWNER $POSTGRES_USER ENCODING 'utf-8';
+EOSQL
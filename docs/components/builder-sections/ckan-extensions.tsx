@@ -81,6 +81,15 @@ export default function CKANExtensionsBuilderSection({
               );
               return;
             }
+            if (
+              config.extensions.includes("XLoader") &&
+              config.extensions.includes("DataStore")
+            ) {
+              toast.error(
+                "You cannot remove the DataStore extension because the XLoader extension depends on it.",
+              );
+              return;
+            }
             updateExtensions(config, setConfig, "DataStore");
           }}
         ></Card>
@@ -89,6 +98,12 @@ export default function CKANExtensionsBuilderSection({
           icon={<TerminalSquareIcon />}
           title="DataPusher+"
           onClick={() => {
+            if (config.extensions.includes("XLoader")) {
+              toast.error(
+                "You cannot add the DataPusher+ extension because it cannot be installed alongside the XLoader extension.",
+              );
+              return;
+            }
             if (config.extensions.includes("DataPusher+")) {
               updateExtensions(config, setConfig, "DataPusher+");
             } else {
@@ -101,6 +116,53 @@ export default function CKANExtensionsBuilderSection({
             }
           }}
         ></Card>
+        <Card
+          className={getExtensionClassName(config, "XLoader")}
+          icon={<TerminalSquareIcon />}
+          title="XLoader"
+          onClick={() => {
+            if (config.extensions.includes("DataPusher+")) {
+              toast.error(
+                "You cannot add the XLoader extension because it cannot be installed alongside the DataPusher+ extension.",
+              );
+              return;
+            }
+            if (config.extensions.includes("XLoader")) {
+              updateExtensions(config, setConfig, "XLoader");
+            } else {
+              updateExtensions(
+                config,
+                setConfig,
+                ["XLoader", "DataStore"],
+                "add",
+              );
+            }
+          }}
+        ></Card>
+        <Card
+          className={getExtensionClassName(config, "ckanext-harvest")}
+          icon={<TerminalSquareIcon />}
+          title="ckanext-harvest"
+          onClick={() => {
+            updateExtensions(config, setConfig, "ckanext-harvest");
+          }}
+        ></Card>
+        <Card
+          className={getExtensionClassName(config, "ckanext-dcat")}
+          icon={<TerminalSquareIcon />}
+          title="ckanext-dcat"
+          onClick={() => {
+            updateExtensions(config, setConfig, "ckanext-dcat");
+          }}
+        ></Card>
+        <Card
+          className={getExtensionClassName(config, "ckanext-spatial")}
+          icon={<TerminalSquareIcon />}
+          title="ckanext-spatial"
+          onClick={() => {
+            updateExtensions(config, setConfig, "ckanext-spatial");
+          }}
+        ></Card>
       </Cards>
     </>
   );
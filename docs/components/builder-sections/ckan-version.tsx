@@ -1,6 +1,7 @@
 import defaultMdxComponents from "fumadocs-ui/mdx";
 import { SailboatIcon } from "lucide-react";
 import { selectedCardClasses } from "../builder";
+import ckanVersions from "../../../ckan-versions.json";
 
 export default function CKANVersionBuilderSection({ config, setConfig }: any) {
   const { Card, Cards } = defaultMdxComponents;
@@ -9,30 +10,23 @@ export default function CKANVersionBuilderSection({ config, setConfig }: any) {
     <>
       <h3>CKAN version</h3>
       <Cards>
-        <Card
-          icon={<SailboatIcon />}
-          title="2.11.5"
-          className={
-            config.ckanVersion === "2.11.5"
-              ? selectedCardClasses
-              : "cursor-pointer"
-          }
-          onClick={() => {
-            setConfig({ ...config, ckanVersion: "2.11.5" });
-          }}
-        ></Card>
-        <Card
-          icon={<SailboatIcon />}
-          title="2.10.10"
-          className={
-            config.ckanVersion === "2.10.10"
-              ? selectedCardClasses
-              : "cursor-pointer"
-          }
-          onClick={() => {
-            setConfig({ ...config, ckanVersion: "2.10.10" });
-          }}
-        ></Card>
+        {ckanVersions.map((ckanVersion) => (
+          <Card
+            key={ckanVersion.version}
+            icon={<SailboatIcon />}
+            title={ckanVersion.version}
+            className={
+              config.ckanVersion === ckanVersion.version
+                ? selectedCardClasses
+                : "cursor-pointer"
+            }
+            onClick={() => {
+              setConfig({ ...config, ckanVersion: ckanVersion.version });
+            }}
+          >
+            Python {ckanVersion.python.min} to {ckanVersion.python.max}
+          </Card>
+        ))}
       </Cards>
     </>
   );
@@ -1,5 +1,5 @@
 import defaultMdxComponents from "fumadocs-ui/mdx";
-import { SailboatIcon, TerminalSquareIcon } from "lucide-react";
+import { DatabaseIcon, SailboatIcon, TerminalSquareIcon } from "lucide-react";
 import { Config, selectedCardClasses } from "../builder";
 
 const getFeatureClassName = (config: Config, featureName: string) => {
@@ -42,6 +42,16 @@ export default function FeaturesBuilderSection({
         >
           Installs the openssh-server package.
         </Card>
+        <Card
+          className={getFeatureClassName(config, "seed-data")}
+          icon={<DatabaseIcon />}
+          title="Seed sample data"
+          onClick={() => {
+            updateFeatures(config, setConfig, "seed-data");
+          }}
+        >
+          Creates sample organizations, groups, datasets and users.
+        </Card>
       </Cards>
     </>
   );
@@ -1,4 +1,4 @@
-import { Config, selectedCardClasses } from "../builder";
+import { Config, defaultCkanVersion, selectedCardClasses } from "../builder";
 import { BarChartBigIcon, SailboatIcon } from "lucide-react";
 import defaultMdxComponents from "fumadocs-ui/mdx";
 
@@ -34,7 +34,7 @@ export default function PresetsBuilderSection({
             });
           }}
         >
-          Installs CKAN with ckan-compose. Default CKAN extension(s) and no extra features
+          Installs CKAN with PostgreSQL, Solr and Redis in Docker. Default CKAN extension(s) and no extra features
           are installed.
         </Card>
         <Card
@@ -49,7 +49,7 @@ export default function PresetsBuilderSection({
             setConfig({
               ...config,
               preset: "dathere-default",
-              ckanVersion: "2.11.5",
+              ckanVersion: defaultCkanVersion,
               extensions: ["ckanext-scheming", "DataStore", "DataPusher+"],
               features: ["enable-ssh"],
             });
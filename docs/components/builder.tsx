@@ -8,6 +8,7 @@ import PresetsBuilderSection from "@/components/builder-sections/presets";
 import CKANVersionBuilderSection from "@/components/builder-sections/ckan-version";
 import CKANExtensionsBuilderSection from "@/components/builder-sections/ckan-extensions";
 import FeaturesBuilderSection from "@/components/builder-sections/features";
+import ckanVersions from "../../ckan-versions.json";
 
 export type Config = {
   preset: string | undefined;
@@ -16,6 +17,9 @@ export type Config = {
   features: string[];
 };
 
+// The CLI embeds the same catalog, so the first entry is also its default CKAN version
+export const defaultCkanVersion = ckanVersions[0].version;
+
 export const selectedCardClasses =
   "bg-blue-100 dark:bg-blue-950 border-blue-300 dark:border-blue-900 border-2";
 
@@ -41,7 +45,7 @@ sudo chmod +x ./ckan-devstaller\n\n# Run ckan-devstaller script\n`;
   const [command, setCommand] = useState("./ckan-devstaller");
   const [config, setConfig] = useState<Config>({
     preset: "ckan-only",
-    ckanVersion: "2.11.5",
+    ckanVersion: defaultCkanVersion,
     extensions: [],
     features: [],
   });
@@ -1,3 +1,4 @@
+import { fileURLToPath } from 'node:url';
 import { createMDX } from 'fumadocs-mdx/next';
 
 const withMDX = createMDX();
@@ -7,6 +8,10 @@ const config = {
   reactStrictMode: true,
   images: {
     unoptimized: true
+  },
+  // Allow importing the CKAN version catalog shared with the CLI from the repository root
+  turbopack: {
+    root: fileURLToPath(new URL('..', import.meta.url))
   }
 };
 
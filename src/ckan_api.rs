@@ -0,0 +1,144 @@
+use crate::{
+    config::{Config, DEFAULT_CKAN_PORT},
+    instance, run,
+    secrets::{self, SecretFile},
+};
+use anyhow::{Result, anyhow};
+use serde_json::{Value, json};
+use std::{
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+use xshell::{Shell, cmd};
+
+/// Local URL of CKAN, using the port of the current installation.
+pub fn ckan_url() -> String {
+    let ckan_port = Config::load_saved()
+        .ok()
+        .flatten()
+        .map_or(DEFAULT_CKAN_PORT, |config| config.ckan_port);
+    format!("http://localhost:{ckan_port}")
+}
+
+/// Create an API token for a CKAN user with the `ckan user token add` command.
+pub fn create_api_token(sh: &Shell, username: &str, token_name: &str) -> Result<String> {
+    let ckan_ini = instance::current().ini_path();
+    let token_command_output = cmd!(
+        sh,
+        "ckan -c {ckan_ini} user token add {username} {token_name}"
+    )
+    .read()?;
+    let tail_output = cmd!(sh, "tail -n 1").stdin(token_command_output).read()?;
+    let api_token = cmd!(sh, "tr -d '\t'").stdin(tail_output).read()?;
+    secrets::register(&api_token);
+    Ok(api_token)
+}
+
+/// Call an action of the CKAN Action API and return its `result` value.
+pub fn action(sh: &Shell, api_token: Option<&str>, name: &str, data: &Value) -> Result<Value> {
+    let url = format!("{}/api/3/action/{name}", ckan_url());
+    // The API token is read by curl from a file so that it does not show up in the process list
+    let headers = authorization_headers(api_token.unwrap_or_default())?;
+    let headers_path = &headers.path;
+    let response = cmd!(
+        sh,
+        "curl -s -X POST -H @{headers_path} -H 'Content-Type: application/json' --data-binary @- {url}"
+    )
+    .quiet()
+    .stdin(data.to_string())
+    .read()?;
+    parse_response(name, &response)
+}
+
+/// Create a resource by uploading a local file, sending every field of `fields` alongside it.
+pub fn upload_resource(
+    sh: &Shell,
+    api_token: &str,
+    file_path: &str,
+    fields: &Value,
+) -> Result<Value> {
+    let url = format!("{}/api/3/action/resource_create", ckan_url());
+    let headers = authorization_headers(api_token)?;
+    let headers_path = &headers.path;
+    let mut form_args = vec![];
+    for (key, value) in fields.as_object().into_iter().flatten() {
+        let value = match value {
+            Value::String(value) => value.clone(),
+            value => value.to_string(),
+        };
+        form_args.push("--form-string".to_string());
+        form_args.push(format!("{key}={value}"));
+    }
+    let upload_field = format!("upload=@{file_path}");
+    let response = cmd!(
+        sh,
+        "curl -s -X POST -H @{headers_path} {form_args...} -F {upload_field} {url}"
+    )
+    .quiet()
+    .read()?;
+    parse_response("resource_create", &response)
+}
+
+/// Check whether CKAN is responding at [`ckan_url`].
+pub fn is_running(sh: &Shell) -> bool {
+    action(sh, None, "status_show", &json!({})).is_ok()
+}
+
+/// Run `f` while CKAN is running, starting a temporary `ckan run` process if it is not already running.
+pub fn with_ckan_running<T>(sh: &Shell, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    let ckan_bin = instance::current().ckan_bin();
+    let ckan_ini = instance::current().ini_path();
+    if is_running(sh) {
+        return f();
+    }
+    println!("Starting a temporary CKAN instance...");
+    let (host, port) = run::listen_address()?;
+    let mut ckan_process = Command::new(&ckan_bin)
+        .args([
+            "-c",
+            &ckan_ini,
+            "run",
+            "--disable-reloader",
+            "--host",
+            host,
+            "--port",
+            &port,
+        ])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    let started = Instant::now();
+    while !is_running(sh) {
+        if started.elapsed() > Duration::from_secs(120) {
+            ckan_process.kill().ok();
+            return Err(anyhow!("CKAN did not start within 120 seconds"));
+        }
+        std::thread::sleep(Duration::from_secs(2));
+    }
+    let result = f();
+    ckan_process.kill()?;
+    ckan_process.wait()?;
+    result
+}
+
+/// Header file for curl's `-H @file` with the Authorization header of an API token.
+fn authorization_headers(api_token: &str) -> Result<SecretFile> {
+    SecretFile::new(&format!("Authorization: {api_token}\n"))
+}
+
+fn parse_response(name: &str, response: &str) -> Result<Value> {
+    let mut response: Value = serde_json::from_str(response).map_err(|_| {
+        anyhow!(
+            "Unexpected response from CKAN for {name}: {}",
+            secrets::redact(response)
+        )
+    })?;
+    if response["success"].as_bool() == Some(true) {
+        Ok(response["result"].take())
+    } else {
+        Err(anyhow!(
+            "{name} failed: {}",
+            secrets::redact(&response["error"].to_string())
+        ))
+    }
+}
@@ -0,0 +1,212 @@
+use crate::{
+    lockfile::Lockfile,
+    python,
+    styles::{step_text, success_text},
+};
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::{fmt, path::Path};
+use xshell::{Shell, cmd};
+
+pub const CKAN_REPO_URL: &str = "https://github.com/ckan/ckan.git";
+
+/// Where CKAN core is installed from
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum CkanSource {
+    /// The official release tag of `ckan_version`
+    #[default]
+    Release,
+    /// A git repository, such as a fork, at a branch, tag or commit (defaults to its default branch)
+    Git {
+        url: String,
+        git_ref: Option<String>,
+    },
+    /// A pull request of ckan/ckan
+    Pr { number: u32 },
+    /// A local checkout, installed in place
+    Path { path: String },
+}
+
+impl CkanSource {
+    /// Parse a `<git-url>@<ref>` given with --ckan-source. The ref is optional, so that
+    /// `git@github.com:user/ckan.git` and `https://user@host/ckan.git` are read as URLs: the `@`
+    /// only starts a ref after the repository path.
+    pub fn git(source: &str) -> CkanSource {
+        let has_path = |url: &str| match url.split_once("://") {
+            Some((_, rest)) => rest.contains('/'),
+            None => url.contains(':') || url.contains('/'),
+        };
+        match source.rsplit_once('@') {
+            Some((url, git_ref)) if has_path(url) && !git_ref.is_empty() => CkanSource::Git {
+                url: url.to_string(),
+                git_ref: Some(git_ref.to_string()),
+            },
+            _ => CkanSource::Git {
+                url: source.to_string(),
+                git_ref: None,
+            },
+        }
+    }
+
+    /// Local checkout given with --ckan-path, which must contain CKAN's setup files.
+    pub fn path(path: &Path) -> Result<CkanSource> {
+        let path = path
+            .canonicalize()
+            .map_err(|e| anyhow!("Could not find the CKAN checkout {}: {e}", path.display()))?;
+        if !path.join("ckan").is_dir() || !path.join("requirements.txt").exists() {
+            return Err(anyhow!(
+                "{} is not a CKAN checkout, it should contain the ckan package and requirements.txt",
+                path.display()
+            ));
+        }
+        Ok(CkanSource::Path {
+            path: path.to_string_lossy().to_string(),
+        })
+    }
+
+    pub fn is_release(&self) -> bool {
+        matches!(self, CkanSource::Release)
+    }
+}
+
+impl fmt::Display for CkanSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CkanSource::Release => write!(f, "the official release"),
+            CkanSource::Git {
+                url,
+                git_ref: Some(git_ref),
+            } => write!(f, "{url} at {git_ref}"),
+            CkanSource::Git { url, git_ref: None } => write!(f, "{url}"),
+            CkanSource::Pr { number } => write!(f, "ckan/ckan pull request #{number}"),
+            CkanSource::Path { path } => write!(f, "the local checkout {path}"),
+        }
+    }
+}
+
+/// Check out the CKAN source into `{src_dir}/ckan` and install it with `pip install -e`, so that
+/// the source tree is the code running in the virtual environment.
+///
+/// A local checkout is symlinked instead of cloned and is never moved to the lockfile's commit.
+pub fn step_install_ckan_source(
+    step_prefix: String,
+    sh: &Shell,
+    source: &CkanSource,
+    ckan_version: &str,
+    src_dir: &str,
+    lockfile: Option<&Lockfile>,
+) -> Result<()> {
+    let ckan_dir = format!("{src_dir}/ckan");
+    let source_text = match source {
+        CkanSource::Release => format!("the ckan-{ckan_version} tag"),
+        _ => source.to_string(),
+    };
+    println!(
+        "\n{} Installing CKAN from {source_text} into {ckan_dir}...",
+        step_text(step_prefix.as_str()),
+    );
+    cmd!(sh, "mkdir -p {src_dir}").run()?;
+    match source {
+        CkanSource::Release => {
+            let tag = format!("ckan-{ckan_version}");
+            cmd!(sh, "git clone --branch {tag} {CKAN_REPO_URL} {ckan_dir}").run()?;
+        }
+        CkanSource::Git { url, git_ref } => {
+            cmd!(sh, "git clone {url} {ckan_dir}").run()?;
+            if let Some(git_ref) = git_ref {
+                cmd!(sh, "git -C {ckan_dir} checkout {git_ref}").run()?;
+            }
+        }
+        CkanSource::Pr { number } => {
+            let pr_head = format!("pull/{number}/head");
+            let pr_branch = format!("pr-{number}");
+            cmd!(sh, "git clone {CKAN_REPO_URL} {ckan_dir}").run()?;
+            cmd!(sh, "git -C {ckan_dir} fetch origin {pr_head}:{pr_branch}").run()?;
+            cmd!(sh, "git -C {ckan_dir} checkout {pr_branch}").run()?;
+        }
+        CkanSource::Path { path } => {
+            cmd!(sh, "ln -s {path} {ckan_dir}").run()?;
+        }
+    }
+    if !matches!(source, CkanSource::Path { .. })
+        && let Some(sha) = lockfile.and_then(Lockfile::ckan_sha)
+    {
+        cmd!(sh, "git -C {ckan_dir} checkout {sha}").run()?;
+    }
+    python::pip_install(sh, &["-r", &format!("{ckan_dir}/requirements.txt")])?;
+    python::pip_install(sh, &["-e", &ckan_dir])?;
+    println!(
+        "{}",
+        success_text(format!("{step_prefix} Installed CKAN from {source_text}.").as_str())
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn git_parts(source: &str) -> (String, Option<String>) {
+        match CkanSource::git(source) {
+            CkanSource::Git { url, git_ref } => (url, git_ref),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn git_splits_the_ref() {
+        assert_eq!(
+            git_parts("https://github.com/user/ckan.git@dev"),
+            (
+                "https://github.com/user/ckan.git".to_string(),
+                Some("dev".to_string())
+            )
+        );
+        assert_eq!(
+            git_parts("https://github.com/user/ckan.git@feature/x"),
+            (
+                "https://github.com/user/ckan.git".to_string(),
+                Some("feature/x".to_string())
+            )
+        );
+    }
+
+    #[test]
+    fn git_defaults_to_no_ref() {
+        assert_eq!(
+            git_parts("https://github.com/user/ckan.git"),
+            ("https://github.com/user/ckan.git".to_string(), None)
+        );
+        assert_eq!(
+            git_parts("https://github.com/user/ckan.git@"),
+            ("https://github.com/user/ckan.git@".to_string(), None)
+        );
+    }
+
+    #[test]
+    fn git_keeps_the_user_of_the_url() {
+        assert_eq!(
+            git_parts("git@github.com:user/ckan.git"),
+            ("git@github.com:user/ckan.git".to_string(), None)
+        );
+        assert_eq!(
+            git_parts("git@github.com:user/ckan.git@2.11"),
+            (
+                "git@github.com:user/ckan.git".to_string(),
+                Some("2.11".to_string())
+            )
+        );
+        assert_eq!(
+            git_parts("https://user@git.example.org/ckan.git"),
+            ("https://user@git.example.org/ckan.git".to_string(), None)
+        );
+        assert_eq!(
+            git_parts("https://user@git.example.org/ckan.git@abc123"),
+            (
+                "https://user@git.example.org/ckan.git".to_string(),
+                Some("abc123".to_string())
+            )
+        );
+    }
+}
@@ -0,0 +1,297 @@
+use crate::{
+    ckan_source::CkanSource, datapusher_plus::DataPusherPlusSettings, instance, python::Installer,
+    services::Services, versions,
+};
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+
+pub const DEFAULT_CKAN_PORT: u16 = 5000;
+
+fn default_ckan_port() -> u16 {
+    DEFAULT_CKAN_PORT
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Sysadmin {
+    pub username: String,
+    pub password: String,
+    pub email: String,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Config {
+    pub ssh: bool,
+    /// Port `ckan run` listens on
+    #[serde(default = "default_ckan_port")]
+    pub ckan_port: u16,
+    /// ckan.site_url, defaults to http://localhost:<ckan_port>
+    #[serde(default)]
+    pub site_url: Option<String>,
+    /// Listen on 0.0.0.0 instead of 127.0.0.1, e.g. to reach CKAN from the host of a VM or WSL
+    #[serde(default)]
+    pub bind_all: bool,
+    /// PostgreSQL, Solr and Redis backends
+    #[serde(default)]
+    pub services: Services,
+    pub ckan_version: String,
+    /// Where CKAN core is installed from, `ckan_version` still selects the Solr image and
+    /// extension refs
+    #[serde(default)]
+    pub ckan_source: CkanSource,
+    /// Tool that creates the virtual environment and installs Python packages
+    #[serde(default)]
+    pub installer: Installer,
+    /// Python version uv creates the virtual environment with, defaults to the newest one
+    /// supported by `ckan_version`
+    #[serde(default)]
+    pub python: Option<String>,
+    pub sysadmin: Sysadmin,
+    pub extension_datastore: bool,
+    pub extension_ckanext_scheming: bool,
+    pub extension_datapusher_plus: bool,
+    #[serde(default)]
+    pub extension_xloader: bool,
+    #[serde(default)]
+    pub extension_ckanext_harvest: bool,
+    #[serde(default)]
+    pub extension_ckanext_dcat: bool,
+    #[serde(default)]
+    pub extension_ckanext_spatial: bool,
+    pub druf_mode: bool,
+    /// DataPusher+ branch, tag or commit to install
+    #[serde(default)]
+    pub dpp_ref: Option<String>,
+    /// qsv release to install for DataPusher+ (defaults to the release matching `dpp_ref`)
+    #[serde(default)]
+    pub qsv_version: Option<String>,
+    /// Named DataPusher+ settings profile that `datapusher_plus` starts from
+    #[serde(default)]
+    pub dpp_profile: Option<String>,
+    #[serde(default)]
+    pub datapusher_plus: DataPusherPlusSettings,
+    pub seed_data: bool,
+    /// Generate random database passwords, sysadmin password and session secrets
+    #[serde(default)]
+    pub secure: bool,
+}
+
+impl Config {
+    /// Load a JSON config file, using `defaults` for any field the file does not set. The fields
+    /// at the `explicit` paths, such as `["services", "runtime"]`, were set by CLI options and keep
+    /// their value from `defaults` over the file's.
+    ///
+    /// A `dpp_profile` set by the file replaces the default DataPusher+ settings before the file's
+    /// own `datapusher_plus` settings are applied.
+    pub fn load(path: &Path, defaults: &Config, explicit: &[&[&str]]) -> Result<Config> {
+        let overrides: Value = serde_json::from_str(&std::fs::read_to_string(path)?)
+            .map_err(|e| anyhow!("Could not parse {}: {e}", path.display()))?;
+        let cli = serde_json::to_value(defaults)?;
+        let mut defaults = defaults.clone();
+        if let Some(profile) = overrides["dpp_profile"].as_str() {
+            defaults.datapusher_plus = DataPusherPlusSettings::profile(profile)?;
+        }
+        let mut config = serde_json::to_value(defaults)?;
+        merge(&mut config, overrides);
+        for path in explicit {
+            if let Some(value) = value_at(&cli, path) {
+                *value_at_mut(&mut config, path) = value.clone();
+            }
+        }
+        serde_json::from_value(config)
+            .map_err(|e| anyhow!("Invalid config file {}: {e}", path.display()))
+    }
+
+    /// Load the effective config written by a previous installation of the current instance, if
+    /// any.
+    pub fn load_saved() -> Result<Option<Config>> {
+        instance::current().saved_config()
+    }
+
+    pub fn site_url(&self) -> String {
+        self.site_url
+            .clone()
+            .unwrap_or_else(|| format!("http://localhost:{}", self.ckan_port))
+    }
+
+    /// Python version of a virtual environment created by uv, `None` for the system python3 used
+    /// by pip.
+    pub fn python_version(&self) -> Option<String> {
+        match self.installer {
+            Installer::Uv => self.python.clone().or_else(|| {
+                versions::find(&self.ckan_version)
+                    .and_then(|version| version.default_python())
+                    .map(str::to_string)
+            }),
+            Installer::Pip => None,
+        }
+    }
+
+    /// Address CKAN listens on. The bundled services always listen on 127.0.0.1.
+    pub fn bind_address(&self) -> &'static str {
+        if self.bind_all {
+            "0.0.0.0"
+        } else {
+            "127.0.0.1"
+        }
+    }
+
+    /// Record whether an extension from the extension registry is installed.
+    pub fn set_extension(&mut self, name: &str, installed: bool) {
+        match name {
+            "DataStore" => self.extension_datastore = installed,
+            "ckanext-scheming" => self.extension_ckanext_scheming = installed,
+            "DataPusher+" => self.extension_datapusher_plus = installed,
+            "XLoader" => self.extension_xloader = installed,
+            "ckanext-harvest" => self.extension_ckanext_harvest = installed,
+            "ckanext-dcat" => self.extension_ckanext_dcat = installed,
+            "ckanext-spatial" => self.extension_ckanext_spatial = installed,
+            _ => {}
+        }
+    }
+
+    /// Names of the extensions from the extension registry that are enabled.
+    pub fn extension_names(&self) -> Vec<&'static str> {
+        [
+            ("DataStore", self.extension_datastore),
+            ("ckanext-scheming", self.extension_ckanext_scheming),
+            ("DataPusher+", self.extension_datapusher_plus),
+            ("XLoader", self.extension_xloader),
+            ("ckanext-harvest", self.extension_ckanext_harvest),
+            ("ckanext-dcat", self.extension_ckanext_dcat),
+            ("ckanext-spatial", self.extension_ckanext_spatial),
+        ]
+        .into_iter()
+        .filter_map(|(name, enabled)| enabled.then_some(name))
+        .collect()
+    }
+
+    /// Write the config, leaving out the sysadmin password when it is kept in the secrets file and
+    /// the describeGPT API key, which is only kept in ckan.ini.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut config = self.clone();
+        if config.secure {
+            config.sysadmin.password = String::new();
+        }
+        config.datapusher_plus.describegpt_api_key =
+            DataPusherPlusSettings::default().describegpt_api_key;
+        std::fs::write(path, serde_json::to_string_pretty(&config)?)?;
+        Ok(())
+    }
+}
+
+fn value_at<'a>(value: &'a Value, path: &[&str]) -> Option<&'a Value> {
+    path.iter().try_fold(value, |value, key| value.get(key))
+}
+
+/// Field at a path of nested objects, created as null if missing.
+fn value_at_mut<'a>(value: &'a mut Value, path: &[&str]) -> &'a mut Value {
+    path.iter().fold(value, |value, key| {
+        if !value.is_object() {
+            *value = Value::Object(Default::default());
+        }
+        value
+            .as_object_mut()
+            .unwrap()
+            .entry(*key)
+            .or_insert(Value::Null)
+    })
+}
+
+fn merge(base: &mut Value, overrides: Value) {
+    match (base, overrides) {
+        (Value::Object(base), Value::Object(overrides)) => {
+            for (key, value) in overrides {
+                merge(base.entry(key).or_insert(Value::Null), value);
+            }
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn cli_config() -> Config {
+        serde_json::from_value(json!({
+            "ssh": false,
+            "ckan_version": "2.11.5",
+            "sysadmin": {
+                "username": "ckan_admin",
+                "password": "password",
+                "email": "ckan_admin@localhost"
+            },
+            "extension_datastore": true,
+            "extension_ckanext_scheming": true,
+            "extension_datapusher_plus": true,
+            "druf_mode": false,
+            "seed_data": false
+        }))
+        .unwrap()
+    }
+
+    /// Load `file` from a temporary file named after the test, so that tests can run in parallel.
+    fn load(test: &str, file: Value, explicit: &[&[&str]]) -> Config {
+        let path = std::env::temp_dir().join(format!(
+            "ckan-devstaller-{test}-{}.json",
+            std::process::id()
+        ));
+        std::fs::write(&path, file.to_string()).unwrap();
+        let config = Config::load(&path, &cli_config(), explicit);
+        std::fs::remove_file(&path).unwrap();
+        config.unwrap()
+    }
+
+    #[test]
+    fn load_uses_the_file_over_defaults() {
+        let config = load(
+            "file-over-defaults",
+            json!({ "ckan_version": "2.10.10", "ckan_port": 5001 }),
+            &[],
+        );
+        assert_eq!(config.ckan_version, "2.10.10");
+        assert_eq!(config.ckan_port, 5001);
+        assert!(config.extension_datapusher_plus);
+    }
+
+    #[test]
+    fn load_keeps_explicit_cli_options_over_the_file() {
+        let config = load(
+            "explicit-over-file",
+            json!({ "ckan_version": "2.10.10", "ckan_port": 5001 }),
+            &[&["ckan_version"]],
+        );
+        assert_eq!(config.ckan_version, "2.11.5");
+        assert_eq!(config.ckan_port, 5001);
+    }
+
+    #[test]
+    fn load_keeps_nested_explicit_cli_options_over_the_file() {
+        let config = load(
+            "nested-explicit-over-file",
+            json!({
+                "services": { "type": "compose", "ports": { "postgres": 5433, "solr": 8984 } }
+            }),
+            &[&["services", "ports", "postgres"]],
+        );
+        let ports = config.services.ports().unwrap();
+        assert_eq!((ports.postgres, ports.solr), (5432, 8984));
+    }
+
+    #[test]
+    fn save_leaves_out_the_describegpt_api_key() {
+        let mut config = cli_config();
+        config.datapusher_plus.describegpt_api_key = "sk-test".to_string();
+        let path = std::env::temp_dir().join(format!(
+            "ckan-devstaller-save-describegpt-{}.json",
+            std::process::id()
+        ));
+        config.save(&path).unwrap();
+        let saved = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(!saved.contains("sk-test"));
+    }
+}
@@ -0,0 +1,109 @@
+use crate::{config::Config, services::Services};
+use anyhow::{Result, anyhow};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use xshell::{Cmd, Shell, cmd};
+
+/// Container engine used to run the bundled services
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ContainerRuntime {
+    /// Docker, run with sudo
+    #[default]
+    Docker,
+    /// Podman, run with sudo
+    Podman,
+    /// Podman, run as the current user
+    PodmanRootless,
+}
+
+impl ContainerRuntime {
+    /// Docker if it is installed, otherwise rootless Podman if it is installed, otherwise Docker.
+    pub fn detect(sh: &Shell) -> ContainerRuntime {
+        let is_installed = |program: &str| {
+            cmd!(sh, "which {program}")
+                .quiet()
+                .ignore_stdout()
+                .ignore_stderr()
+                .run()
+                .is_ok()
+        };
+        if !is_installed("docker") && is_installed("podman") {
+            ContainerRuntime::PodmanRootless
+        } else {
+            ContainerRuntime::Docker
+        }
+    }
+
+    /// Runtime of the bundled services of the current installation, detected if the installation
+    /// did not record one.
+    pub fn installed(sh: &Shell) -> Result<ContainerRuntime> {
+        match Config::load_saved()?.map(|config| config.services) {
+            Some(Services::Compose { runtime, .. }) => Ok(runtime),
+            _ => Ok(ContainerRuntime::detect(sh)),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ContainerRuntime::Docker => "Docker",
+            ContainerRuntime::Podman => "Podman",
+            ContainerRuntime::PodmanRootless => "rootless Podman",
+        }
+    }
+
+    /// Command running the container engine CLI.
+    pub fn cmd<'a>(self, sh: &'a Shell) -> Cmd<'a> {
+        match self {
+            ContainerRuntime::Docker => cmd!(sh, "sudo docker"),
+            ContainerRuntime::Podman => cmd!(sh, "sudo podman"),
+            ContainerRuntime::PodmanRootless => cmd!(sh, "podman"),
+        }
+    }
+
+    /// Command running compose files with the container engine.
+    pub fn compose_cmd<'a>(self, sh: &'a Shell) -> Cmd<'a> {
+        match self {
+            ContainerRuntime::Docker => cmd!(sh, "sudo docker compose"),
+            ContainerRuntime::Podman => cmd!(sh, "sudo podman-compose"),
+            ContainerRuntime::PodmanRootless => cmd!(sh, "podman-compose"),
+        }
+    }
+
+    /// ID of the container with the given name.
+    pub fn container_id(self, sh: &Shell, name: &str) -> Result<String> {
+        let name_filter = format!("name=^{name}$");
+        let container_id = self
+            .cmd(sh)
+            .args(["ps", "-aq", "--filter", &name_filter])
+            .read()?;
+        if container_id.is_empty() {
+            return Err(anyhow!(
+                "Could not find the {name} {} container, make sure the ckan-devstaller services are running",
+                self.name()
+            ));
+        }
+        Ok(container_id)
+    }
+
+    /// Copy a file between the host and a container, using `<container>:<path>` for container paths.
+    pub fn cp(self, sh: &Shell, source: &str, destination: &str) -> Result<()> {
+        self.cmd(sh).args(["cp", source, destination]).run()?;
+        Ok(())
+    }
+
+    /// Run a command in a container.
+    pub fn exec(self, sh: &Shell, container_id: &str, args: &[&str]) -> Result<()> {
+        self.cmd(sh).args(["exec", container_id]).args(args).run()?;
+        Ok(())
+    }
+
+    /// Run a command in a container and return its output.
+    pub fn exec_read(self, sh: &Shell, container_id: &str, args: &[&str]) -> Result<String> {
+        Ok(self
+            .cmd(sh)
+            .args(["exec", container_id])
+            .args(args)
+            .read()?)
+    }
+}
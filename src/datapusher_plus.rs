@@ -0,0 +1,321 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Named DataPusher+ settings profiles, usable with `--dpp-profile` and the `dpp_profile` config field
+pub const PROFILES: &[&str] = &["default", "pii-screening", "large-files"];
+
+/// `ckanext.datapusher_plus.*` options written to ckan.ini when installing DataPusher+
+///
+/// The API token is created during the installation and DRUF mode is set with the `druf_mode`
+/// config field, so neither is part of these settings.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DataPusherPlusSettings {
+    pub use_proxy: bool,
+    pub download_proxy: String,
+    pub ssl_verify: bool,
+    pub upload_log_level: String,
+    pub formats: String,
+    pub pii_screening: bool,
+    pub pii_found_abort: bool,
+    pub pii_regex_resource_id_or_alias: String,
+    pub pii_show_candidates: bool,
+    pub pii_quick_screen: bool,
+    pub qsv_bin: String,
+    pub preview_rows: u64,
+    pub download_timeout: u64,
+    pub max_content_length: u64,
+    pub chunk_size: u64,
+    pub default_excel_sheet: i64,
+    pub sort_and_dupe_check: bool,
+    pub dedup: bool,
+    pub unsafe_prefix: String,
+    pub reserved_colnames: String,
+    pub prefer_dmy: bool,
+    pub ignore_file_hash: bool,
+    pub auto_index_threshold: u64,
+    pub auto_index_dates: bool,
+    pub auto_unique_index: bool,
+    pub summary_stats_options: String,
+    pub add_summary_stats_resource: bool,
+    pub summary_stats_with_preview: bool,
+    pub qsv_stats_string_max_length: u64,
+    pub qsv_dates_whitelist: String,
+    pub qsv_freq_limit: u64,
+    pub auto_alias: bool,
+    pub auto_alias_unique: bool,
+    pub copy_readbuffer_size: u64,
+    /// qsv types mapped to PostgreSQL types
+    pub type_mapping: BTreeMap<String, String>,
+    pub auto_spatial_simplication: bool,
+    pub spatial_simplication_relative_tolerance: f64,
+    pub latitude_fields: String,
+    pub longitude_fields: String,
+    pub jinja2_bytecode_cache_dir: String,
+    pub auto_unzip_one_file: bool,
+    /// Token for an OpenAI API compatible service used by describeGPT
+    pub describegpt_api_key: String,
+    pub file_bin: String,
+    pub enable_form_redirect: bool,
+}
+
+impl Default for DataPusherPlusSettings {
+    fn default() -> Self {
+        DataPusherPlusSettings {
+            use_proxy: false,
+            download_proxy: String::new(),
+            ssl_verify: false,
+            upload_log_level: "INFO".to_string(),
+            formats: "csv tsv tab ssv xls xlsx xlsxb xlsm ods geojson shp qgis zip".to_string(),
+            pii_screening: false,
+            pii_found_abort: false,
+            pii_regex_resource_id_or_alias: String::new(),
+            pii_show_candidates: false,
+            pii_quick_screen: false,
+            qsv_bin: "/usr/local/bin/qsvdp".to_string(),
+            preview_rows: 100,
+            download_timeout: 300,
+            max_content_length: 1256000000000,
+            chunk_size: 16384,
+            default_excel_sheet: 0,
+            sort_and_dupe_check: true,
+            dedup: false,
+            unsafe_prefix: "unsafe_".to_string(),
+            reserved_colnames: "_id".to_string(),
+            prefer_dmy: false,
+            ignore_file_hash: true,
+            auto_index_threshold: 3,
+            auto_index_dates: true,
+            auto_unique_index: true,
+            summary_stats_options: String::new(),
+            add_summary_stats_resource: false,
+            summary_stats_with_preview: false,
+            qsv_stats_string_max_length: 32767,
+            qsv_dates_whitelist: "date,time,due,open,close,created".to_string(),
+            qsv_freq_limit: 10,
+            auto_alias: true,
+            auto_alias_unique: false,
+            copy_readbuffer_size: 1048576,
+            type_mapping: [
+                ("String", "text"),
+                ("Integer", "numeric"),
+                ("Float", "numeric"),
+                ("DateTime", "timestamp"),
+                ("Date", "date"),
+                ("NULL", "text"),
+            ]
+            .into_iter()
+            .map(|(qsv_type, postgres_type)| (qsv_type.to_string(), postgres_type.to_string()))
+            .collect(),
+            auto_spatial_simplication: true,
+            spatial_simplication_relative_tolerance: 0.1,
+            latitude_fields: "latitude,lat".to_string(),
+            longitude_fields: "longitude,long,lon".to_string(),
+            jinja2_bytecode_cache_dir: "/tmp/jinja2_butecode_cache".to_string(),
+            auto_unzip_one_file: true,
+            describegpt_api_key: "<Token for OpenAI API compatible service>".to_string(),
+            file_bin: "/usr/bin/file".to_string(),
+            enable_form_redirect: true,
+        }
+    }
+}
+
+impl DataPusherPlusSettings {
+    /// Settings of a named profile from [`PROFILES`].
+    pub fn profile(name: &str) -> Result<DataPusherPlusSettings> {
+        let defaults = DataPusherPlusSettings::default();
+        match name {
+            "default" => Ok(defaults),
+            // Screen uploads for personally identifiable information and list what was found
+            "pii-screening" => Ok(DataPusherPlusSettings {
+                pii_screening: true,
+                pii_show_candidates: true,
+                ..defaults
+            }),
+            // Allow slow downloads and copy large files in bigger chunks, skipping the costly
+            // sort and duplicate check
+            "large-files" => Ok(DataPusherPlusSettings {
+                download_timeout: 3600,
+                chunk_size: 1048576,
+                copy_readbuffer_size: 16777216,
+                sort_and_dupe_check: false,
+                ..defaults
+            }),
+            _ => Err(anyhow!(
+                "Unknown DataPusher+ profile {name}, use one of {}",
+                PROFILES.join(", ")
+            )),
+        }
+    }
+
+    /// ckan.ini options for these settings.
+    pub fn ini_entries(&self) -> Result<Vec<(&'static str, String)>> {
+        Ok(vec![
+            (
+                "ckanext.datapusher_plus.use_proxy",
+                self.use_proxy.to_string(),
+            ),
+            (
+                "ckanext.datapusher_plus.download_proxy",
+                self.download_proxy.clone(),
+            ),
+            (
+                "ckanext.datapusher_plus.ssl_verify",
+                self.ssl_verify.to_string(),
+            ),
+            (
+                "ckanext.datapusher_plus.upload_log_level",
+                self.upload_log_level.clone(),
+            ),
+            ("ckanext.datapusher_plus.formats", self.formats.clone()),
+            (
+                "ckanext.datapusher_plus.pii_screening",
+                self.pii_screening.to_string(),
+            ),
+            (
+                "ckanext.datapusher_plus.pii_found_abort",
+                self.pii_found_abort.to_string(),
+            ),
+            (
+                "ckanext.datapusher_plus.pii_regex_resource_id_or_alias",
+                self.pii_regex_resource_id_or_alias.clone(),
+            ),
+            (
+                "ckanext.datapusher_plus.pii_show_candidates",
+                self.pii_show_candidates.to_string(),
+            ),
+            (
+                "ckanext.datapusher_plus.pii_quick_screen",
+                self.pii_quick_screen.to_string(),
+            ),
+            ("ckanext.datapusher_plus.qsv_bin", self.qsv_bin.clone()),
+            (
+                "ckanext.datapusher_plus.preview_rows",
+                self.preview_rows.to_string(),
+            ),
+            (
+                "ckanext.datapusher_plus.download_timeout",
+                self.download_timeout.to_string(),
+            ),
+            (
+                "ckanext.datapusher_plus.max_content_length",
+                self.max_content_length.to_string(),
+            ),
+            (
+                "ckanext.datapusher_plus.chunk_size",
+                self.chunk_size.to_string(),
+            ),
+            (
+                "ckanext.datapusher_plus.default_excel_sheet",
+                self.default_excel_sheet.to_string(),
+            ),
+            (
+                "ckanext.datapusher_plus.sort_and_dupe_check",
+                self.sort_and_dupe_check.to_string(),
+            ),
+            ("ckanext.datapusher_plus.dedup", self.dedup.to_string()),
+            (
+                "ckanext.datapusher_plus.unsafe_prefix",
+                self.unsafe_prefix.clone(),
+            ),
+            (
+                "ckanext.datapusher_plus.reserved_colnames",
+                self.reserved_colnames.clone(),
+            ),
+            (
+                "ckanext.datapusher_plus.prefer_dmy",
+                self.prefer_dmy.to_string(),
+            ),
+            (
+                "ckanext.datapusher_plus.ignore_file_hash",
+                self.ignore_file_hash.to_string(),
+            ),
+            (
+                "ckanext.datapusher_plus.auto_index_threshold",
+                self.auto_index_threshold.to_string(),
+            ),
+            (
+                "ckanext.datapusher_plus.auto_index_dates",
+                self.auto_index_dates.to_string(),
+            ),
+            (
+                "ckanext.datapusher_plus.auto_unique_index",
+                self.auto_unique_index.to_string(),
+            ),
+            (
+                "ckanext.datapusher_plus.summary_stats_options",
+                self.summary_stats_options.clone(),
+            ),
+            (
+                "ckanext.datapusher_plus.add_summary_stats_resource",
+                self.add_summary_stats_resource.to_string(),
+            ),
+            (
+                "ckanext.datapusher_plus.summary_stats_with_preview",
+                self.summary_stats_with_preview.to_string(),
+            ),
+            (
+                "ckanext.datapusher_plus.qsv_stats_string_max_length",
+                self.qsv_stats_string_max_length.to_string(),
+            ),
+            (
+                "ckanext.datapusher_plus.qsv_dates_whitelist",
+                self.qsv_dates_whitelist.clone(),
+            ),
+            (
+                "ckanext.datapusher_plus.qsv_freq_limit",
+                self.qsv_freq_limit.to_string(),
+            ),
+            (
+                "ckanext.datapusher_plus.auto_alias",
+                self.auto_alias.to_string(),
+            ),
+            (
+                "ckanext.datapusher_plus.auto_alias_unique",
+                self.auto_alias_unique.to_string(),
+            ),
+            (
+                "ckanext.datapusher_plus.copy_readbuffer_size",
+                self.copy_readbuffer_size.to_string(),
+            ),
+            (
+                "ckanext.datapusher_plus.type_mapping",
+                serde_json::to_string(&self.type_mapping)?,
+            ),
+            (
+                "ckanext.datapusher_plus.auto_spatial_simplication",
+                self.auto_spatial_simplication.to_string(),
+            ),
+            (
+                "ckanext.datapusher_plus.spatial_simplication_relative_tolerance",
+                self.spatial_simplication_relative_tolerance.to_string(),
+            ),
+            (
+                "ckanext.datapusher_plus.latitude_fields",
+                self.latitude_fields.clone(),
+            ),
+            (
+                "ckanext.datapusher_plus.longitude_fields",
+                self.longitude_fields.clone(),
+            ),
+            (
+                "ckanext.datapusher_plus.jinja2_bytecode_cache_dir",
+                self.jinja2_bytecode_cache_dir.clone(),
+            ),
+            (
+                "ckanext.datapusher_plus.auto_unzip_one_file",
+                self.auto_unzip_one_file.to_string(),
+            ),
+            (
+                "ckanext.datapusher_plus.describeGPT_api_key",
+                self.describegpt_api_key.clone(),
+            ),
+            ("ckanext.datapusher_plus.file_bin", self.file_bin.clone()),
+            (
+                "ckanext.datapusher_plus.enable_form_redirect",
+                self.enable_form_redirect.to_string(),
+            ),
+        ])
+    }
+}
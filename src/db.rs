@@ -0,0 +1,328 @@
+use crate::{
+    config::Config,
+    container::ContainerRuntime,
+    instance, secrets,
+    services::Services,
+    styles::{highlighted_text, important_text, step_text, success_text},
+};
+use anyhow::{Result, anyhow};
+use inquire::Confirm;
+use std::path::{Path, PathBuf};
+use xshell::{Shell, cmd};
+
+const DATABASES: [&str; 2] = ["ckan_default", "datastore_default"];
+
+pub fn backups_dir(username: &str) -> PathBuf {
+    PathBuf::from(instance::current().backups_dir(username))
+}
+
+pub fn postgres_container_id(sh: &Shell, runtime: ContainerRuntime) -> Result<String> {
+    runtime.container_id(sh, &instance::current().container_name("postgres"))
+}
+
+/// PostgreSQL server the databases are dumped from and restored into
+enum DatabaseServer {
+    /// The bundled PostgreSQL container
+    Container {
+        runtime: ContainerRuntime,
+        container_id: String,
+    },
+    /// A server given with --services external, reached with the connection URLs of the instance
+    External { services: Services, datastore: bool },
+}
+
+impl DatabaseServer {
+    fn current(sh: &Shell) -> Result<DatabaseServer> {
+        match Config::load_saved()? {
+            Some(config) if config.services.is_external() => Ok(DatabaseServer::External {
+                services: config.services,
+                datastore: config.extension_datastore,
+            }),
+            _ => {
+                let runtime = ContainerRuntime::installed(sh)?;
+                Ok(DatabaseServer::Container {
+                    runtime,
+                    container_id: postgres_container_id(sh, runtime)?,
+                })
+            }
+        }
+    }
+
+    /// Whether one of the [`DATABASES`] exists. External servers only have a DataStore database
+    /// when the DataStore extension is installed.
+    fn exists(&self, sh: &Shell, database: &str) -> Result<bool> {
+        match self {
+            DatabaseServer::Container {
+                runtime,
+                container_id,
+            } => database_exists(sh, *runtime, container_id, database),
+            DatabaseServer::External { datastore, .. } => {
+                Ok(database != "datastore_default" || *datastore)
+            }
+        }
+    }
+
+    /// Connection URL and password of one of the [`DATABASES`] on an external server.
+    fn external_url(services: &Services, database: &str) -> Result<(String, Option<String>)> {
+        let url = match database {
+            "datastore_default" => services.datastore_write_url()?,
+            _ => services.postgres_url()?,
+        };
+        Ok(secrets::split_postgres_password(&url))
+    }
+
+    /// Dump a database in the custom format of pg_dump into `local_dump_path`.
+    fn dump(&self, sh: &Shell, database: &str, local_dump_path: &Path) -> Result<()> {
+        match self {
+            DatabaseServer::Container {
+                runtime,
+                container_id,
+            } => {
+                let dump_path = format!("/tmp/{database}.dump");
+                runtime.exec(
+                    sh,
+                    container_id,
+                    &[
+                        "pg_dump",
+                        "-U",
+                        "ckan_default",
+                        "-Fc",
+                        "-f",
+                        &dump_path,
+                        database,
+                    ],
+                )?;
+                runtime.cp(
+                    sh,
+                    &format!("{container_id}:{dump_path}"),
+                    &local_dump_path.to_string_lossy(),
+                )?;
+                runtime.exec(sh, container_id, &["rm", &dump_path])?;
+            }
+            DatabaseServer::External { services, .. } => {
+                let (url, password) = DatabaseServer::external_url(services, database)?;
+                let mut pg_dump = cmd!(sh, "pg_dump -Fc -f {local_dump_path} {url}");
+                if let Some(password) = password {
+                    pg_dump = pg_dump.env("PGPASSWORD", password);
+                }
+                pg_dump.run()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Replace the contents of a database with a dump created by [`DatabaseServer::dump`].
+    fn restore(&self, sh: &Shell, database: &str, local_dump_path: &Path) -> Result<()> {
+        match self {
+            DatabaseServer::Container {
+                runtime,
+                container_id,
+            } => {
+                let dump_path = format!("/tmp/{database}.dump");
+                runtime.cp(
+                    sh,
+                    &local_dump_path.to_string_lossy(),
+                    &format!("{container_id}:{dump_path}"),
+                )?;
+                runtime.exec(
+                    sh,
+                    container_id,
+                    &[
+                        "pg_restore",
+                        "-U",
+                        "ckan_default",
+                        "--clean",
+                        "--if-exists",
+                        "--no-owner",
+                        "-d",
+                        database,
+                        &dump_path,
+                    ],
+                )?;
+                runtime.exec(sh, container_id, &["rm", &dump_path])?;
+            }
+            DatabaseServer::External { services, .. } => {
+                let (url, password) = DatabaseServer::external_url(services, database)?;
+                let mut pg_restore = cmd!(
+                    sh,
+                    "pg_restore --clean --if-exists --no-owner -d {url} {local_dump_path}"
+                );
+                if let Some(password) = password {
+                    pg_restore = pg_restore.env("PGPASSWORD", password);
+                }
+                pg_restore.run()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Dump the CKAN and DataStore databases and the file storage into `<backups dir>/<name>.tar.gz`,
+/// returning the path of the archive.
+pub fn backup(sh: &Shell, username: &str, name: Option<String>) -> Result<PathBuf> {
+    let storage_dir = instance::current().storage_dir();
+    let timestamp = cmd!(sh, "date +%Y%m%d-%H%M%S").read()?;
+    let name = match name {
+        Some(name) => format!("{name}-{timestamp}"),
+        None => timestamp,
+    };
+    let backups_dir = backups_dir(username);
+    std::fs::create_dir_all(&backups_dir)?;
+    let archive = backups_dir.join(format!("{name}.tar.gz"));
+    let work_dir = PathBuf::from(format!("/tmp/ckan-devstaller-backup-{name}"));
+    std::fs::create_dir_all(&work_dir)?;
+
+    let server = DatabaseServer::current(sh)?;
+    for (i, database) in DATABASES.iter().enumerate() {
+        println!(
+            "\n{} Dumping the {} database...",
+            step_text(format!("{}.", i + 1).as_str()),
+            highlighted_text(database)
+        );
+        if !server.exists(sh, database)? {
+            println!("Skipping {database}, it does not exist.");
+            continue;
+        }
+        server.dump(sh, database, &work_dir.join(format!("{database}.dump")))?;
+        println!(
+            "{}",
+            success_text(format!("{}. Dumped the {database} database.", i + 1).as_str())
+        );
+    }
+
+    println!(
+        "\n{} Archiving {storage_dir} and the database dumps...",
+        step_text("3.")
+    );
+    if std::fs::exists(&storage_dir)? {
+        let storage_archive = work_dir.join("storage.tar.gz");
+        cmd!(sh, "sudo tar -czf {storage_archive} -C {storage_dir} .").run()?;
+    }
+    cmd!(sh, "sudo tar -czf {archive} -C {work_dir} .").run()?;
+    cmd!(sh, "sudo chown {username} {archive}").run()?;
+    cmd!(sh, "sudo rm -rf {work_dir}").run()?;
+    println!(
+        "{}",
+        success_text(format!("3. Created the backup {}.", archive.display()).as_str())
+    );
+    Ok(archive)
+}
+
+/// Restore a backup created by [`backup`]. `name` may be a path to an archive, the name of an
+/// archive in the backups directory, or the `--name` given to `db backup` (the latest matching
+/// backup is used).
+pub fn restore(sh: &Shell, username: &str, name: &str, skip_confirmation: bool) -> Result<()> {
+    let ckan_ini = instance::current().ini_path();
+    let storage_dir = instance::current().storage_dir();
+    let archive = find_backup(username, name)?;
+    if !skip_confirmation {
+        let restore_confirmation = Confirm::new(
+            format!(
+                "Are you sure you want to restore {}? This replaces the current CKAN and DataStore databases and file storage.",
+                archive.display()
+            )
+            .as_str(),
+        )
+        .with_help_message("Stop any running CKAN instance before restoring a backup.")
+        .prompt()?;
+        if !restore_confirmation {
+            println!("Cancelling command.");
+            return Ok(());
+        }
+    }
+    let work_dir = PathBuf::from("/tmp/ckan-devstaller-restore");
+    cmd!(sh, "sudo rm -rf {work_dir}").run()?;
+    std::fs::create_dir_all(&work_dir)?;
+    cmd!(sh, "tar -xzf {archive} -C {work_dir}").run()?;
+
+    let server = DatabaseServer::current(sh)?;
+    for (i, database) in DATABASES.iter().enumerate() {
+        println!(
+            "\n{} Restoring the {} database...",
+            step_text(format!("{}.", i + 1).as_str()),
+            highlighted_text(database)
+        );
+        let local_dump_path = work_dir.join(format!("{database}.dump"));
+        if !local_dump_path.exists() {
+            println!("Skipping {database}, the backup does not contain it.");
+            continue;
+        }
+        server.restore(sh, database, &local_dump_path)?;
+        println!(
+            "{}",
+            success_text(format!("{}. Restored the {database} database.", i + 1).as_str())
+        );
+    }
+
+    println!(
+        "\n{} Restoring {storage_dir} and rebuilding the search index...",
+        step_text("3.")
+    );
+    let storage_archive = work_dir.join("storage.tar.gz");
+    if storage_archive.exists() {
+        cmd!(sh, "sudo rm -rf {storage_dir}").run()?;
+        cmd!(sh, "sudo mkdir -p {storage_dir}").run()?;
+        cmd!(sh, "sudo tar -xzf {storage_archive} -C {storage_dir}").run()?;
+        cmd!(sh, "sudo chown -R {username}.{username} {storage_dir}").run()?;
+    }
+    cmd!(sh, "ckan -c {ckan_ini} search-index rebuild").run()?;
+    cmd!(sh, "sudo rm -rf {work_dir}").run()?;
+    println!(
+        "{}",
+        success_text(format!("3. Restored the backup {}.", archive.display()).as_str())
+    );
+    Ok(())
+}
+
+fn database_exists(
+    sh: &Shell,
+    runtime: ContainerRuntime,
+    postgres_container_id: &str,
+    database: &str,
+) -> Result<bool> {
+    let query = format!("SELECT 1 FROM pg_database WHERE datname='{database}'");
+    let output = runtime.exec_read(
+        sh,
+        postgres_container_id,
+        &[
+            "psql",
+            "-U",
+            "ckan_default",
+            "-d",
+            "postgres",
+            "-tAc",
+            &query,
+        ],
+    )?;
+    Ok(output.trim() == "1")
+}
+
+fn find_backup(username: &str, name: &str) -> Result<PathBuf> {
+    let path = Path::new(name);
+    if path.is_file() {
+        return Ok(path.to_path_buf());
+    }
+    let backups_dir = backups_dir(username);
+    let exact = backups_dir.join(format!("{name}.tar.gz"));
+    if exact.is_file() {
+        return Ok(exact);
+    }
+    // Backup names end with a sortable timestamp, so the last match is the latest backup
+    let mut matches = std::fs::read_dir(&backups_dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|file_name| file_name.to_str())
+                .is_some_and(|file_name| {
+                    file_name.starts_with(&format!("{name}-")) && file_name.ends_with(".tar.gz")
+                })
+        })
+        .collect::<Vec<_>>();
+    matches.sort();
+    matches.pop().ok_or_else(|| {
+        anyhow!(
+            "Could not find a backup named {name} in {}",
+            important_text(backups_dir.display().to_string().as_str())
+        )
+    })
+}
@@ -0,0 +1,612 @@
+use crate::{
+    config::Config,
+    instance, python,
+    steps::{
+        DEFAULT_DATAPUSHER_PLUS_REF, is_local_checkout, qsv_version_for,
+        step_install_ckanext_scheming_extension, step_install_datapusher_plus_extension,
+        step_install_datastore_extension, step_install_dcat_extension,
+        step_install_harvest_extension, step_install_spatial_extension,
+        step_install_xloader_extension,
+    },
+    styles::{highlighted_text, important_text, step_text, success_text},
+    versions,
+};
+use anyhow::{Result, anyhow};
+use inquire::Confirm;
+use std::path::{Path, PathBuf};
+use xshell::{Shell, cmd};
+
+/// A CKAN extension that ckan-devstaller knows how to install
+pub struct Extension {
+    /// Name used with `--extensions` and the `extensions` subcommands
+    pub name: &'static str,
+    /// Directory of the extension in /usr/lib/ckan/default/src, `None` for extensions shipped with CKAN
+    pub src_dir: Option<&'static str>,
+    /// Plugins added to ckan.plugins
+    pub plugins: &'static [&'static str],
+    /// Prefixes of the ckan.ini options set when installing the extension
+    pub config_prefixes: &'static [&'static str],
+    /// Extensions that must be installed first
+    pub depends_on: &'static [&'static str],
+    /// Extensions that cannot be installed alongside this extension
+    pub conflicts_with: &'static [&'static str],
+    /// Plugins with database migrations to run with `ckan db upgrade -p`
+    pub migration_plugins: &'static [&'static str],
+}
+
+pub const EXTENSIONS: &[Extension] = &[
+    Extension {
+        name: "DataStore",
+        src_dir: None,
+        plugins: &["datastore"],
+        config_prefixes: &["ckan.datastore."],
+        depends_on: &[],
+        conflicts_with: &[],
+        migration_plugins: &[],
+    },
+    Extension {
+        name: "ckanext-scheming",
+        src_dir: Some("ckanext-scheming"),
+        plugins: &["scheming_datasets"],
+        config_prefixes: &["scheming."],
+        depends_on: &[],
+        conflicts_with: &[],
+        migration_plugins: &[],
+    },
+    Extension {
+        name: "DataPusher+",
+        src_dir: Some("datapusher-plus"),
+        plugins: &["datapusher_plus"],
+        config_prefixes: &["ckanext.datapusher_plus.", "scheming.dataset_schemas"],
+        depends_on: &["DataStore", "ckanext-scheming"],
+        conflicts_with: &["XLoader"],
+        migration_plugins: &["datapusher_plus"],
+    },
+    Extension {
+        name: "XLoader",
+        src_dir: Some("ckanext-xloader"),
+        plugins: &["xloader"],
+        config_prefixes: &["ckanext.xloader."],
+        depends_on: &["DataStore"],
+        conflicts_with: &["DataPusher+"],
+        migration_plugins: &[],
+    },
+    Extension {
+        name: "ckanext-harvest",
+        src_dir: Some("ckanext-harvest"),
+        plugins: &["harvest", "ckan_harvester"],
+        config_prefixes: &["ckan.harvest."],
+        depends_on: &[],
+        conflicts_with: &[],
+        migration_plugins: &["harvest"],
+    },
+    Extension {
+        name: "ckanext-dcat",
+        src_dir: Some("ckanext-dcat"),
+        plugins: &["dcat", "structured_data"],
+        config_prefixes: &["ckanext.dcat."],
+        depends_on: &[],
+        conflicts_with: &[],
+        migration_plugins: &[],
+    },
+    Extension {
+        name: "ckanext-spatial",
+        src_dir: Some("ckanext-spatial"),
+        plugins: &["spatial_metadata", "spatial_query"],
+        config_prefixes: &["ckanext.spatial."],
+        depends_on: &[],
+        conflicts_with: &[],
+        migration_plugins: &[],
+    },
+];
+
+/// Fail if any two of the given extensions are mutually exclusive.
+pub fn check_conflicts(names: &[&str]) -> Result<()> {
+    for name in names {
+        if let Some(extension) = find_extension(name)
+            && let Some(conflict) = extension
+                .conflicts_with
+                .iter()
+                .find(|conflict| names.contains(conflict))
+        {
+            return Err(anyhow!(
+                "The {} and {conflict} extensions cannot be installed together",
+                extension.name
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Fail if any of the given extensions depends on an extension that is not among them.
+pub fn check_dependencies(names: &[&str]) -> Result<()> {
+    for name in names {
+        if let Some(extension) = find_extension(name)
+            && let Some(dependency) = extension
+                .depends_on
+                .iter()
+                .find(|dependency| !names.contains(dependency))
+        {
+            return Err(anyhow!(
+                "The {} extension depends on the {dependency} extension, install them together",
+                extension.name
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Find an extension by its name or source directory.
+pub fn find_extension(name: &str) -> Option<&'static Extension> {
+    EXTENSIONS.iter().find(|extension| {
+        extension.name.eq_ignore_ascii_case(name) || extension.src_dir == Some(name)
+    })
+}
+
+impl Extension {
+    /// Whether every plugin of the extension is enabled in ckan.plugins.
+    pub fn is_installed(&self) -> Result<bool> {
+        let plugins = installed_plugins()?;
+        Ok(self
+            .plugins
+            .iter()
+            .all(|plugin| plugins.iter().any(|p| p == plugin)))
+    }
+}
+
+/// Plugins enabled in the ckan.plugins option of /etc/ckan/default/ckan.ini.
+pub fn installed_plugins() -> Result<Vec<String>> {
+    let ckan_ini = instance::current().ini_path();
+    let conf = ini::Ini::load_from_file(&ckan_ini)?;
+    Ok(conf
+        .get_from(Some("app:main"), "ckan.plugins")
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(str::to_string)
+        .collect())
+}
+
+/// Remove plugins from ckan.plugins and every option starting with one of `config_prefixes`.
+fn remove_from_config(plugins: &[&str], config_prefixes: &[&str]) -> Result<()> {
+    let ckan_ini = instance::current().ini_path();
+    let mut conf = ini::Ini::load_from_file(&ckan_ini)?;
+    let app_main_section = conf
+        .section_mut(Some("app:main"))
+        .ok_or_else(|| anyhow!("Missing [app:main] section in {ckan_ini}"))?;
+    let ckan_plugins = app_main_section
+        .get("ckan.plugins")
+        .unwrap_or_default()
+        .split_whitespace()
+        .filter(|plugin| !plugins.contains(plugin))
+        .collect::<Vec<_>>()
+        .join(" ");
+    app_main_section.insert("ckan.plugins", ckan_plugins);
+    let keys = app_main_section
+        .iter()
+        .map(|(key, _)| key.to_string())
+        .filter(|key| config_prefixes.iter().any(|prefix| key.starts_with(prefix)))
+        .collect::<Vec<_>>();
+    for key in keys {
+        app_main_section.remove(key);
+    }
+    conf.write_to_file(&ckan_ini)?;
+    Ok(())
+}
+
+/// Add plugins to the end of ckan.plugins, skipping plugins that are already enabled.
+fn add_to_config(plugins: &[String]) -> Result<()> {
+    let ckan_ini = instance::current().ini_path();
+    let mut conf = ini::Ini::load_from_file(&ckan_ini)?;
+    let app_main_section = conf
+        .section_mut(Some("app:main"))
+        .ok_or_else(|| anyhow!("Missing [app:main] section in {ckan_ini}"))?;
+    let mut ckan_plugins = app_main_section
+        .get("ckan.plugins")
+        .unwrap_or_default()
+        .to_string();
+    for plugin in plugins {
+        if !ckan_plugins.split_whitespace().any(|p| p == plugin) {
+            ckan_plugins.push_str(format!(" {plugin}").as_str());
+        }
+    }
+    app_main_section.insert("ckan.plugins", ckan_plugins);
+    conf.write_to_file(&ckan_ini)?;
+    Ok(())
+}
+
+/// Remove the TAB format that the DataPusher+ step adds to CKAN's resource_formats.json by
+/// restoring the file from git. A local checkout was never changed.
+fn revert_datapusher_plus_resource_formats(sh: &Shell) -> Result<()> {
+    let src_dir = instance::current().src_dir();
+    if is_local_checkout(&src_dir) {
+        return Ok(());
+    }
+    let ckan_dir = format!("{src_dir}/ckan");
+    cmd!(
+        sh,
+        "git -C {ckan_dir} checkout -- ckan/config/resource_formats.json"
+    )
+    .run()?;
+    Ok(())
+}
+
+/// Install an extension from the registry (along with the extensions it depends on) or from a git
+/// URL into an existing installation.
+pub fn add_extension(
+    sh: &Shell,
+    username: &str,
+    sysadmin_username: &str,
+    name: &str,
+    git_ref: Option<&str>,
+    plugins: &[String],
+) -> Result<()> {
+    let Some(extension) = find_extension(name) else {
+        return add_git_extension(sh, name, git_ref, plugins);
+    };
+    if extension.is_installed()? {
+        println!(
+            "{}",
+            success_text(
+                format!("The {} extension is already installed.", extension.name).as_str()
+            )
+        );
+        return Ok(());
+    }
+    for conflict in extension.conflicts_with {
+        if find_extension(conflict).is_some_and(|conflict| conflict.is_installed().unwrap_or(false))
+        {
+            return Err(anyhow!(
+                "The {} extension cannot be installed alongside the installed {conflict} extension, remove it first",
+                extension.name
+            ));
+        }
+    }
+    for dependency in extension.depends_on {
+        add_extension(sh, username, sysadmin_username, dependency, None, &[])?;
+    }
+    // Installed with the settings the instance was installed with, e.g. the DataPusher+ profile
+    let config = Config::load_saved()?;
+    let services = config
+        .as_ref()
+        .map(|config| config.services.clone())
+        .unwrap_or_default();
+    match extension.name {
+        "DataStore" => {
+            step_install_datastore_extension("1.".to_string(), sh, username.to_string(), &services)?
+        }
+        "ckanext-scheming" => {
+            step_install_ckanext_scheming_extension("1.".to_string(), sh, git_ref)?
+        }
+        "DataPusher+" => {
+            let config = config.as_ref();
+            let datapusher_plus_ref = git_ref
+                .or(config.and_then(|config| config.dpp_ref.as_deref()))
+                .or(config
+                    .and_then(|config| versions::find(&config.ckan_version))
+                    .and_then(|version| version.extension_ref("DataPusher+")))
+                .unwrap_or(DEFAULT_DATAPUSHER_PLUS_REF);
+            let qsv_version = config
+                .and_then(|config| config.qsv_version.as_deref())
+                .unwrap_or(qsv_version_for(datapusher_plus_ref));
+            step_install_datapusher_plus_extension(
+                "1.".to_string(),
+                sh,
+                sysadmin_username.to_string(),
+                username.to_string(),
+                config.is_some_and(|config| config.druf_mode),
+                &config
+                    .map(|config| config.datapusher_plus.clone())
+                    .unwrap_or_default(),
+                datapusher_plus_ref,
+                qsv_version,
+            )?;
+            // The describeGPT API key is not kept in the saved config
+            println!(
+                "{}",
+                important_text(
+                    format!(
+                        "Set ckanext.datapusher_plus.describegpt_api_key in {} to use describeGPT.",
+                        instance::current().ini_path()
+                    )
+                    .as_str()
+                )
+            );
+        }
+        "XLoader" => step_install_xloader_extension(
+            "1.".to_string(),
+            sh,
+            sysadmin_username.to_string(),
+            git_ref,
+            &services,
+        )?,
+        "ckanext-harvest" => {
+            step_install_harvest_extension("1.".to_string(), sh, git_ref, &services)?
+        }
+        "ckanext-dcat" => step_install_dcat_extension("1.".to_string(), sh, git_ref)?,
+        "ckanext-spatial" => {
+            step_install_spatial_extension("1.".to_string(), sh, username, git_ref, &services)?
+        }
+        _ => unreachable!("every registered extension has an install step"),
+    }
+    Ok(())
+}
+
+fn add_git_extension(
+    sh: &Shell,
+    url: &str,
+    git_ref: Option<&str>,
+    plugins: &[String],
+) -> Result<()> {
+    let ckan_ini = instance::current().ini_path();
+    if !url.contains("://") && !url.starts_with("git@") {
+        return Err(anyhow!(
+            "Unknown extension {url}, use one of {} or a git URL",
+            EXTENSIONS
+                .iter()
+                .map(|extension| extension.name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    let name = url
+        .trim_end_matches('/')
+        .trim_end_matches(".git")
+        .rsplit('/')
+        .next()
+        .unwrap_or(url)
+        .to_lowercase();
+    println!(
+        "\n{} Installing the {} extension...",
+        step_text("1."),
+        highlighted_text(name.as_str())
+    );
+    sh.change_dir(instance::current().src_dir());
+    python::install_git_editable(sh, &name, url, git_ref)?;
+    let requirements_path = Path::new(&instance::current().src_dir())
+        .join(&name)
+        .join("requirements.txt");
+    if requirements_path.exists() {
+        python::pip_install(sh, &["-r", &requirements_path.to_string_lossy()])?;
+    }
+    if plugins.is_empty() {
+        println!(
+            "{}",
+            important_text(
+                format!("No plugins were given with --plugins, add the plugins of {name} to ckan.plugins in {ckan_ini}.").as_str()
+            )
+        );
+    } else {
+        add_to_config(plugins)?;
+    }
+    println!(
+        "{}",
+        success_text(format!("1. Installed the {name} extension.").as_str())
+    );
+    Ok(())
+}
+
+/// Disable an extension's plugins, remove its ckan.ini options, uninstall its Python package and
+/// revert extension-specific edits.
+pub fn remove_extension(
+    sh: &Shell,
+    name: &str,
+    plugins: &[String],
+    skip_confirmation: bool,
+) -> Result<()> {
+    let ckan_ini = instance::current().ini_path();
+    let extension = find_extension(name);
+    let src_dir = match extension {
+        Some(extension) => extension.src_dir.map(str::to_string),
+        None => Some(check_git_extension_dir(name)?),
+    };
+    if let Some(extension) = extension {
+        for dependent in EXTENSIONS
+            .iter()
+            .filter(|dependent| dependent.depends_on.contains(&extension.name))
+        {
+            if dependent.is_installed()? {
+                return Err(anyhow!(
+                    "The {} extension depends on {}, remove it first",
+                    dependent.name,
+                    extension.name
+                ));
+            }
+        }
+    }
+    if !skip_confirmation {
+        let remove_confirmation = Confirm::new(
+            format!("Are you sure you want to remove the {name} extension?").as_str(),
+        )
+        .with_help_message(
+            format!("Its plugins and options are removed from {ckan_ini} and its source directory is deleted.").as_str(),
+        )
+        .prompt()?;
+        if !remove_confirmation {
+            println!("Cancelling command.");
+            return Ok(());
+        }
+    }
+    println!(
+        "\n{} Removing the {} extension...",
+        step_text("1."),
+        highlighted_text(name)
+    );
+    match extension {
+        Some(extension) => remove_from_config(extension.plugins, extension.config_prefixes)?,
+        None => remove_from_config(&plugins.iter().map(String::as_str).collect::<Vec<_>>(), &[])?,
+    }
+    if extension.is_some_and(|extension| extension.name == "DataPusher+") {
+        revert_datapusher_plus_resource_formats(sh)?;
+    }
+    if let Some(src_dir) = src_dir {
+        let src_path = Path::new(&instance::current().src_dir()).join(&src_dir);
+        python::uninstall(sh, &src_dir)?;
+        if src_path.exists() {
+            std::fs::remove_dir_all(src_path)?;
+        }
+    }
+    println!(
+        "{}",
+        success_text(format!("1. Removed the {name} extension.").as_str())
+    );
+    Ok(())
+}
+
+/// Check that an extension installed from a git URL is a checkout directly under the source
+/// directory, so that removing it cannot delete CKAN core or anything outside the source directory.
+fn check_git_extension_dir(name: &str) -> Result<String> {
+    if name.is_empty() || name.contains('/') || name.contains("..") {
+        return Err(anyhow!(
+            "Invalid extension name {name}, pass the name of its directory in {}",
+            instance::current().src_dir()
+        ));
+    }
+    if name == "ckan" {
+        return Err(anyhow!(
+            "CKAN core cannot be removed as an extension, see `ckan-devstaller uninstall`"
+        ));
+    }
+    let src_path = Path::new(&instance::current().src_dir()).join(name);
+    if !src_path.join(".git").exists() {
+        return Err(anyhow!(
+            "No extension named {name}, {} is not a git checkout",
+            src_path.display()
+        ));
+    }
+    Ok(name.to_string())
+}
+
+/// Update the extension flags of the saved config to match the extensions that are installed.
+pub fn sync_saved_config() -> Result<()> {
+    if let Some(mut config) = Config::load_saved()? {
+        for extension in EXTENSIONS {
+            config.set_extension(extension.name, extension.is_installed()?);
+        }
+        config.save(Path::new(&instance::current().config_path()))?;
+    }
+    Ok(())
+}
+
+/// Git repositories of installed extensions in /usr/lib/ckan/default/src, excluding CKAN itself.
+pub fn installed_extension_dirs() -> Result<Vec<PathBuf>> {
+    let mut dirs = vec![];
+    for entry in std::fs::read_dir(instance::current().src_dir())? {
+        let dir = entry?.path();
+        if dir.join(".git").exists() && !dir.ends_with("ckan") {
+            dirs.push(dir);
+        }
+    }
+    dirs.sort();
+    Ok(dirs)
+}
+
+/// Fetch an installed extension, show its pending commits and move it to `git_ref` (defaults to
+/// the latest commit of its default branch). Its requirements are then reinstalled and its
+/// migrations are run.
+pub fn update_extensions(sh: &Shell, name: Option<&str>, git_ref: Option<&str>) -> Result<()> {
+    let dirs = match name {
+        Some(name) => {
+            let src_dir = find_extension(name)
+                .map(|extension| {
+                    extension.src_dir.ok_or_else(|| {
+                        anyhow!("The {name} extension is shipped with CKAN and is updated with it")
+                    })
+                })
+                .transpose()?
+                .unwrap_or(name);
+            let dir = Path::new(&instance::current().src_dir()).join(src_dir);
+            if !dir.join(".git").exists() {
+                return Err(anyhow!(
+                    "The {name} extension is not installed in {}",
+                    instance::current().src_dir()
+                ));
+            }
+            vec![dir]
+        }
+        None => installed_extension_dirs()?,
+    };
+    for (i, dir) in dirs.iter().enumerate() {
+        let step_prefix = format!("{}.", i + 1);
+        update_extension(sh, step_prefix, dir, git_ref)?;
+    }
+    Ok(())
+}
+
+fn update_extension(
+    sh: &Shell,
+    step_prefix: String,
+    dir: &Path,
+    git_ref: Option<&str>,
+) -> Result<()> {
+    let ckan_ini = instance::current().ini_path();
+    let dir_name = dir
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    println!(
+        "\n{} Updating {}...",
+        step_text(step_prefix.as_str()),
+        highlighted_text(dir_name.as_str())
+    );
+    sh.change_dir(dir);
+    cmd!(sh, "git fetch --tags origin").run()?;
+    let git_ref = match git_ref {
+        Some(git_ref) => git_ref.to_string(),
+        None => {
+            let default_branch = cmd!(sh, "git rev-parse --abbrev-ref origin/HEAD").read()?;
+            default_branch
+                .strip_prefix("origin/")
+                .unwrap_or(&default_branch)
+                .to_string()
+        }
+    };
+    let remote_branch = format!("origin/{git_ref}");
+    let is_branch = cmd!(sh, "git rev-parse --verify --quiet {remote_branch}")
+        .quiet()
+        .ignore_stdout()
+        .run()
+        .is_ok();
+    let target = if is_branch {
+        remote_branch.clone()
+    } else {
+        git_ref.clone()
+    };
+    let pending_commits = cmd!(sh, "git log --oneline HEAD..{target}").read()?;
+    if pending_commits.is_empty() {
+        println!(
+            "{}",
+            success_text(format!("{step_prefix} {dir_name} is already up to date.").as_str())
+        );
+        return Ok(());
+    }
+    println!("Pending commits for {dir_name}:\n{pending_commits}");
+    cmd!(sh, "git checkout {git_ref}").run()?;
+    if is_branch {
+        cmd!(sh, "git merge --ff-only {remote_branch}").run()?;
+    }
+    if dir.join("requirements.txt").exists() {
+        python::pip_install(sh, &["-r", "requirements.txt"])?;
+    }
+    python::pip_install(sh, &["-e", "."])?;
+    let migration_plugins = find_extension(&dir_name)
+        .map(|extension| extension.migration_plugins)
+        .unwrap_or_default();
+    for plugin in migration_plugins {
+        cmd!(sh, "ckan -c {ckan_ini} db upgrade -p {plugin}").run()?;
+    }
+    if migration_plugins.is_empty() && find_extension(&dir_name).is_none() {
+        println!(
+            "{}",
+            important_text(
+                format!("Run ckan db upgrade -p <plugin> if {dir_name} has database migrations.")
+                    .as_str()
+            )
+        );
+    }
+    println!(
+        "{}",
+        success_text(format!("{step_prefix} Updated {dir_name} to {git_ref}.").as_str())
+    );
+    Ok(())
+}
@@ -0,0 +1,348 @@
+use crate::{
+    config::Config,
+    container::ContainerRuntime,
+    services::{ServicePorts, Services},
+    styles::{highlighted_text, important_text, step_text, success_text},
+};
+use anyhow::{Result, anyhow};
+use inquire::Confirm;
+use std::{path::Path, sync::OnceLock};
+use xshell::{Shell, cmd};
+
+/// Name of the instance used when --instance is not given, matching CKAN's documented paths
+pub const DEFAULT_INSTANCE: &str = "default";
+
+static CURRENT: OnceLock<Instance> = OnceLock::new();
+
+/// A named CKAN installation with its own virtual environment, config, file storage and compose
+/// project
+#[derive(Clone)]
+pub struct Instance {
+    pub name: String,
+}
+
+impl Instance {
+    pub fn new(name: &str) -> Result<Instance> {
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+        {
+            return Err(anyhow!(
+                "Invalid instance name {name}, use lowercase letters, digits, - and _"
+            ));
+        }
+        Ok(Instance {
+            name: name.to_string(),
+        })
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.name == DEFAULT_INSTANCE
+    }
+
+    /// Virtual environment of CKAN, e.g. /usr/lib/ckan/default
+    pub fn venv_dir(&self) -> String {
+        format!("/usr/lib/ckan/{}", self.name)
+    }
+
+    /// Source directory of CKAN and its extensions
+    pub fn src_dir(&self) -> String {
+        format!("{}/src", self.venv_dir())
+    }
+
+    pub fn ckan_bin(&self) -> String {
+        format!("{}/bin/ckan", self.venv_dir())
+    }
+
+    pub fn config_dir(&self) -> String {
+        format!("/etc/ckan/{}", self.name)
+    }
+
+    pub fn ini_path(&self) -> String {
+        format!("{}/ckan.ini", self.config_dir())
+    }
+
+    /// Effective configuration written at the end of an installation
+    pub fn config_path(&self) -> String {
+        format!("{}/ckan-devstaller.json", self.config_dir())
+    }
+
+    /// Random passwords and secrets of an installation made with --secure
+    pub fn secrets_path(&self) -> String {
+        format!("{}/ckan-devstaller.secrets.json", self.config_dir())
+    }
+
+    /// Lockfile written at the end of an installation
+    pub fn lockfile_path(&self) -> String {
+        format!("{}/ckan-devstaller.lock.json", self.config_dir())
+    }
+
+    /// File storage of CKAN, used for ckan.storage_path
+    pub fn storage_dir(&self) -> String {
+        format!("/var/lib/ckan/{}", self.name)
+    }
+
+    /// docker compose project of the bundled services
+    pub fn compose_project(&self) -> String {
+        self.suffixed("ckan-devstaller-project")
+    }
+
+    /// Directory the bundled compose file is written to.
+    pub fn compose_dir(&self, username: &str) -> String {
+        format!(
+            "/home/{username}/{}",
+            self.suffixed("ckan-devstaller-compose")
+        )
+    }
+
+    pub fn backups_dir(&self, username: &str) -> String {
+        format!(
+            "/home/{username}/{}",
+            self.suffixed("ckan-devstaller-backups")
+        )
+    }
+
+    /// Name of a container of the bundled services, e.g. `postgres`.
+    pub fn container_name(&self, service: &str) -> String {
+        format!("{}-{service}", self.compose_project())
+    }
+
+    /// Effective config of the instance, if it was installed by this version of ckan-devstaller.
+    pub fn saved_config(&self) -> Result<Option<Config>> {
+        let config_path = self.config_path();
+        if !std::fs::exists(&config_path)? {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_str(&std::fs::read_to_string(
+            config_path,
+        )?)?))
+    }
+
+    /// The default instance keeps the unsuffixed names of earlier ckan-devstaller versions.
+    fn suffixed(&self, base: &str) -> String {
+        if self.is_default() {
+            base.to_string()
+        } else {
+            format!("{base}-{}", self.name)
+        }
+    }
+}
+
+/// Select the instance every command operates on. Must be called before [`current`].
+pub fn set_current(instance: Instance) -> Result<()> {
+    CURRENT.set(instance).map_err(|instance| {
+        anyhow!(
+            "Cannot select the {} instance, the {} instance is already in use",
+            instance.name,
+            current().name
+        )
+    })
+}
+
+/// Instance selected with --instance.
+pub fn current() -> &'static Instance {
+    CURRENT.get_or_init(|| Instance {
+        name: DEFAULT_INSTANCE.to_string(),
+    })
+}
+
+/// Instances with a ckan.ini in /etc/ckan, sorted by name.
+pub fn installed() -> Result<Vec<Instance>> {
+    if !std::fs::exists("/etc/ckan")? {
+        return Ok(vec![]);
+    }
+    let mut instances = std::fs::read_dir("/etc/ckan")?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().join("ckan.ini").exists())
+        .filter_map(|entry| Instance::new(&entry.file_name().to_string_lossy()).ok())
+        .collect::<Vec<_>>();
+    instances.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(instances)
+}
+
+/// Ports used by the CKAN and bundled services of the other installed instances.
+fn ports_in_use(instance: &Instance) -> Result<Vec<u16>> {
+    let mut ports = vec![];
+    for other in installed()? {
+        if other.name == instance.name {
+            continue;
+        }
+        if let Some(config) = other.saved_config()? {
+            ports.push(config.ckan_port);
+            if let Some(service_ports) = config.services.ports() {
+                ports.extend([
+                    service_ports.postgres,
+                    service_ports.solr,
+                    service_ports.redis,
+                ]);
+            }
+        }
+    }
+    Ok(ports)
+}
+
+/// Offset the default ports of `ckan_port` and `service_ports` until none of them is used by
+/// another instance.
+pub fn free_ports(
+    instance: &Instance,
+    ckan_port: u16,
+    service_ports: ServicePorts,
+) -> Result<(u16, ServicePorts)> {
+    let ports_in_use = ports_in_use(instance)?;
+    for offset in 0..100 {
+        let candidate = (
+            ckan_port + offset,
+            ServicePorts {
+                postgres: service_ports.postgres + offset,
+                solr: service_ports.solr + offset,
+                redis: service_ports.redis + offset,
+            },
+        );
+        if [
+            candidate.0,
+            candidate.1.postgres,
+            candidate.1.solr,
+            candidate.1.redis,
+        ]
+        .iter()
+        .all(|port| !ports_in_use.contains(port))
+        {
+            return Ok(candidate);
+        }
+    }
+    Err(anyhow!(
+        "Could not find free ports for the {} instance, choose them with --ckan-port, --postgres-port, --solr-port and --redis-port",
+        instance.name
+    ))
+}
+
+/// Check that the ports of `config` are not used by another instance.
+pub fn check_ports(instance: &Instance, config: &Config) -> Result<()> {
+    let ports_in_use = ports_in_use(instance)?;
+    let mut ports = vec![config.ckan_port];
+    if let Some(service_ports) = config.services.ports() {
+        ports.extend([
+            service_ports.postgres,
+            service_ports.solr,
+            service_ports.redis,
+        ]);
+    }
+    match ports.iter().find(|port| ports_in_use.contains(port)) {
+        Some(port) => Err(anyhow!(
+            "Port {port} is already used by another ckan-devstaller instance, see `ckan-devstaller instances list`"
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Whether any directory of the instance exists.
+pub fn exists(instance: &Instance) -> bool {
+    Path::new(&instance.config_dir()).exists() || Path::new(&instance.venv_dir()).exists()
+}
+
+/// Stop the bundled services of an instance and delete their volumes.
+pub fn remove_services(sh: &Shell, instance: &Instance, username: &str) -> Result<()> {
+    let compose_dir = instance.compose_dir(username);
+    if !std::fs::exists(&compose_dir)? {
+        return Ok(());
+    }
+    let runtime = match instance.saved_config()?.map(|config| config.services) {
+        Some(Services::Compose { runtime, .. }) => runtime,
+        _ => ContainerRuntime::detect(sh),
+    };
+    sh.change_dir(compose_dir);
+    runtime
+        .compose_cmd(sh)
+        .args(["-p", &instance.compose_project(), "down", "--volumes"])
+        .ignore_status()
+        .run()?;
+    Ok(())
+}
+
+/// Print the installed instances with their CKAN version, site URL and services.
+pub fn list() -> Result<()> {
+    let instances = installed()?;
+    if instances.is_empty() {
+        println!("No CKAN instances are installed.");
+        return Ok(());
+    }
+    for instance in instances {
+        let Some(config) = instance.saved_config()? else {
+            println!(
+                "{} (installed by an earlier version of ckan-devstaller)",
+                highlighted_text(&instance.name)
+            );
+            continue;
+        };
+        let services = match &config.services {
+            Services::Compose { runtime, ports } => format!(
+                "{} (PostgreSQL {}, Solr {}, Redis {})",
+                runtime.name(),
+                ports.postgres,
+                ports.solr,
+                ports.redis
+            ),
+            Services::External(external) => format!(
+                "external (PostgreSQL {}, Solr {}, Redis {})",
+                external.postgres_url, external.solr_url, external.redis_url
+            ),
+        };
+        println!(
+            "{} CKAN {} at {} with {services}",
+            highlighted_text(&instance.name),
+            config.ckan_version,
+            config.site_url()
+        );
+    }
+    Ok(())
+}
+
+/// Stop the services of an instance and delete its virtual environment, config, file storage and
+/// compose files.
+pub fn remove(sh: &Shell, username: &str, name: &str, skip_confirmation: bool) -> Result<()> {
+    let instance = Instance::new(name)?;
+    if !exists(&instance) {
+        return Err(anyhow!(
+            "No instance named {name}, see `ckan-devstaller instances list`"
+        ));
+    }
+    if !skip_confirmation {
+        let remove_confirmation =
+            Confirm::new(format!("Are you sure you want to remove the {name} instance?").as_str())
+                .with_help_message(
+                    format!(
+                        "Its services and their data are removed along with {}, {} and {}.",
+                        instance.venv_dir(),
+                        instance.config_dir(),
+                        instance.storage_dir()
+                    )
+                    .as_str(),
+                )
+                .prompt()?;
+        if !remove_confirmation {
+            println!("Cancelling command.");
+            return Ok(());
+        }
+    }
+    println!(
+        "\n{} Removing the {} instance...",
+        step_text("1."),
+        important_text(name)
+    );
+    remove_services(sh, &instance, username)?;
+    let venv_dir = instance.venv_dir();
+    let config_dir = instance.config_dir();
+    let storage_dir = instance.storage_dir();
+    let compose_dir = instance.compose_dir(username);
+    cmd!(
+        sh,
+        "sudo rm -rf {venv_dir} {config_dir} {storage_dir} {compose_dir}"
+    )
+    .run()?;
+    println!(
+        "{}",
+        success_text(format!("1. Removed the {name} instance.").as_str())
+    );
+    Ok(())
+}
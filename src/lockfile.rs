@@ -0,0 +1,160 @@
+use crate::{
+    config::Config,
+    instance, python,
+    services::{self, Services},
+    styles::{step_text, success_text},
+};
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::Path};
+use xshell::{Shell, cmd};
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LockedRepo {
+    pub url: String,
+    pub sha: String,
+}
+
+/// Everything needed to reproduce an installation
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Lockfile {
+    /// Repositories cloned into /usr/lib/ckan/default/src, keyed by directory name
+    pub repos: BTreeMap<String, LockedRepo>,
+    pub qsv_version: Option<String>,
+    /// `pip freeze` of /usr/lib/ckan/default
+    pub requirements: Vec<String>,
+    /// Images of the bundled services pinned by digest, keyed by the compose .env variable
+    /// selecting them
+    #[serde(default)]
+    pub images: BTreeMap<String, String>,
+}
+
+impl Lockfile {
+    pub fn load(path: &Path) -> Result<Lockfile> {
+        serde_json::from_str(&std::fs::read_to_string(path)?)
+            .map_err(|e| anyhow!("Could not parse the lockfile {}: {e}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Locked commit of a repository in /usr/lib/ckan/default/src.
+    pub fn repo_sha(&self, name: &str) -> Option<&str> {
+        self.repos.get(name).map(|repo| repo.sha.as_str())
+    }
+
+    /// Locked commit of CKAN core.
+    pub fn ckan_sha(&self) -> Option<&str> {
+        self.repo_sha("ckan")
+    }
+
+    /// Pinned requirements, leaving out CKAN and the extensions, which are installed editable from their
+    /// locked commits.
+    pub fn pinned_requirements(&self) -> Vec<&str> {
+        self.requirements
+            .iter()
+            .map(String::as_str)
+            .filter(|requirement| !requirement.starts_with("-e ") && !requirement.starts_with('#'))
+            .collect()
+    }
+}
+
+/// Record the git SHAs of every cloned repository, a `pip freeze` of the virtual environment, the
+/// qsv version and the images of the bundled services.
+pub fn read_installed(sh: &Shell) -> Result<Lockfile> {
+    let src_dir = instance::current().src_dir();
+    let mut repos = BTreeMap::new();
+    for entry in std::fs::read_dir(&src_dir)? {
+        let repo_dir = entry?.path();
+        if !repo_dir.join(".git").exists() {
+            continue;
+        }
+        let name = repo_dir
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        repos.insert(name, locked_repo(sh, &repo_dir)?);
+    }
+    let qsv_version = cmd!(sh, "/usr/local/bin/qsvdp --version")
+        .quiet()
+        .ignore_stderr()
+        .read()
+        .ok()
+        .and_then(|output| parse_version(&output));
+    let requirements = python::pip(sh)
+        .arg("freeze")
+        .quiet()
+        .read()?
+        .lines()
+        .map(str::to_string)
+        .collect();
+    let images = match Config::load_saved()?.map(|config| config.services) {
+        Some(Services::Compose { runtime, .. }) => services::service_images(sh, runtime)?,
+        _ => BTreeMap::new(),
+    };
+    Ok(Lockfile {
+        repos,
+        qsv_version,
+        requirements,
+        images,
+    })
+}
+
+pub fn step_write_lockfile(step_prefix: String, sh: &Shell) -> Result<()> {
+    let lockfile_path = instance::current().lockfile_path();
+    println!(
+        "\n{} Writing the lockfile {}...",
+        step_text(step_prefix.as_str()),
+        lockfile_path
+    );
+    read_installed(sh)?.save(Path::new(&lockfile_path))?;
+    println!(
+        "{}",
+        success_text(format!("{step_prefix} Wrote the lockfile {lockfile_path}.").as_str())
+    );
+    Ok(())
+}
+
+/// Pin every Python package to the version recorded in the lockfile.
+pub fn step_install_locked_requirements(
+    step_prefix: String,
+    sh: &Shell,
+    lockfile: &Lockfile,
+) -> Result<()> {
+    println!(
+        "\n{} Pinning Python packages to the lockfile...",
+        step_text(step_prefix.as_str())
+    );
+    let requirements_path = "/tmp/ckan-devstaller-locked-requirements.txt";
+    std::fs::write(requirements_path, lockfile.pinned_requirements().join("\n"))?;
+    python::pip_install(sh, &["-r", requirements_path])?;
+    std::fs::remove_file(requirements_path)?;
+    println!(
+        "{}",
+        success_text(format!("{step_prefix} Pinned Python packages to the lockfile.").as_str())
+    );
+    Ok(())
+}
+
+fn locked_repo(sh: &Shell, repo_dir: &Path) -> Result<LockedRepo> {
+    Ok(LockedRepo {
+        // A local CKAN checkout given with --ckan-path may not have an origin remote
+        url: cmd!(sh, "git -C {repo_dir} remote get-url origin")
+            .ignore_stderr()
+            .read()
+            .unwrap_or_default(),
+        sha: cmd!(sh, "git -C {repo_dir} rev-parse HEAD").read()?,
+    })
+}
+
+/// First token that looks like a version number, e.g. `4.0.0` from `qsvdp 4.0.0-mimalloc-...`.
+fn parse_version(output: &str) -> Option<String> {
+    output
+        .split_whitespace()
+        .map(|token| token.trim_start_matches('v'))
+        .find(|token| token.starts_with(|c: char| c.is_ascii_digit()))
+        .map(|token| token.split('-').next().unwrap_or(token).to_string())
+}
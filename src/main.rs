@@ -1,22 +1,58 @@
+mod ckan_api;
+mod ckan_source;
+mod config;
+mod container;
+mod datapusher_plus;
+mod db;
+mod extensions;
+mod instance;
+mod lockfile;
+mod python;
 mod questions;
+mod run;
+mod secrets;
+mod seed;
+mod services;
+mod snapshot;
 mod steps;
 mod styles;
+mod upgrade;
+mod validation;
+mod verify;
+mod versions;
 
 use crate::{
-    questions::{question_ckan_version, question_ssh, question_sysadmin},
+    ckan_source::CkanSource,
+    config::{Config, DEFAULT_CKAN_PORT, Sysadmin},
+    container::ContainerRuntime,
+    datapusher_plus::DataPusherPlusSettings,
+    instance::{DEFAULT_INSTANCE, Instance},
+    lockfile::Lockfile,
+    python::{Installer, PYTHON_VERSIONS},
+    questions::{
+        question_ckan_version, question_datapusher_plus_settings, question_ssh, question_sysadmin,
+    },
+    services::{
+        ExternalServices, ServicePorts, Services, ServicesKind, step_configure_services,
+        step_start_compose_services,
+    },
     steps::{
-        step_install_ahoy, step_install_and_run_ckan_compose,
-        step_install_ckanext_scheming_extension, step_install_curl,
-        step_install_datapusher_plus_extension, step_install_datastore_extension,
-        step_install_docker, step_install_openssh, step_package_updates,
+        DEFAULT_DATAPUSHER_PLUS_REF, qsv_version_for, step_install_ckanext_scheming_extension,
+        step_install_curl, step_install_datapusher_plus_extension,
+        step_install_datastore_extension, step_install_dcat_extension, step_install_docker,
+        step_install_harvest_extension, step_install_openssh, step_install_podman,
+        step_install_spatial_extension, step_install_xloader_extension, step_package_updates,
     },
     styles::{important_text, step_text, success_text},
 };
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 use clap::{Parser, Subcommand};
 use human_panic::{metadata, setup_panic};
 use inquire::Confirm;
-use std::{path::PathBuf, str::FromStr};
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 use xshell::cmd;
 use xshell_venv::{Shell, VirtualEnv};
 
@@ -33,12 +69,87 @@ struct Args {
     #[arg(short, long)]
     /// CKAN version to install defined by semantic versioning from official releases from https://github.com/ckan/ckan
     ckan_version: Option<String>,
+    /// Install CKAN core from a git repository, such as a fork, at a branch, tag or commit: <git-url>@<ref>
+    #[arg(long, conflicts_with_all = ["ckan_pr", "ckan_path"])]
+    ckan_source: Option<String>,
+    /// Install CKAN core from a pull request of ckan/ckan
+    #[arg(long, conflicts_with = "ckan_path")]
+    ckan_pr: Option<u32>,
+    /// Install CKAN core in place from a local checkout
+    #[arg(long)]
+    ckan_path: Option<PathBuf>,
+    /// Tool that creates the virtual environment and installs CKAN and its extensions (defaults to uv)
+    #[arg(long, value_enum)]
+    installer: Option<Installer>,
+    /// Python version of the virtual environment, fetched by uv (defaults to the newest version supported by the CKAN version)
+    #[arg(long, value_parser = clap::builder::PossibleValuesParser::new(PYTHON_VERSIONS))]
+    python: Option<String>,
     /// List of CKAN extensions to install, separated by spaces
     #[arg(short, long, value_parser, num_args = 1.., value_delimiter = ' ')]
     extensions: Option<Vec<String>>,
     /// List of custom features, separated by spaces
     #[arg(short, long, value_parser, num_args = 1.., value_delimiter = ' ')]
     features: Option<Vec<String>>,
+    /// DataPusher+ branch, tag or commit to install
+    #[arg(long)]
+    dpp_ref: Option<String>,
+    /// qsv release to install for DataPusher+ (defaults to the release known to work with --dpp-ref)
+    #[arg(long)]
+    qsv_version: Option<String>,
+    /// Named DataPusher+ settings profile: default, pii-screening or large-files
+    #[arg(long)]
+    dpp_profile: Option<String>,
+    /// Start PostgreSQL, Solr and Redis with the bundled docker compose file or use existing servers (defaults to compose)
+    #[arg(long, value_enum)]
+    services: Option<ServicesKind>,
+    /// Container runtime used to run the bundled services (defaults to Docker, or rootless Podman if only Podman is installed)
+    #[arg(long, value_enum)]
+    container_runtime: Option<ContainerRuntime>,
+    /// Connection URL of the CKAN database when using external services
+    #[arg(long, required_if_eq("services", "external"))]
+    postgres_url: Option<String>,
+    /// Connection URL used to write to the DataStore database when using external services (defaults to the datastore_default database on the --postgres-url server)
+    #[arg(long)]
+    datastore_write_url: Option<String>,
+    /// Read-only connection URL of the DataStore database when using external services
+    #[arg(long)]
+    datastore_read_url: Option<String>,
+    /// URL of the Solr core when using external services
+    #[arg(long, required_if_eq("services", "external"))]
+    solr_url: Option<String>,
+    /// URL of the Redis server when using external services
+    #[arg(long, required_if_eq("services", "external"))]
+    redis_url: Option<String>,
+    /// Port CKAN is served on (defaults to 5000, or the next free ports if other instances use them)
+    #[arg(long)]
+    ckan_port: Option<u16>,
+    /// Public URL of the CKAN site, used for ckan.site_url (defaults to http://localhost:<ckan port>)
+    #[arg(long)]
+    site_url: Option<String>,
+    /// Host port of the bundled PostgreSQL service (defaults to 5432)
+    #[arg(long)]
+    postgres_port: Option<u16>,
+    /// Host port of the bundled Solr service (defaults to 8983)
+    #[arg(long)]
+    solr_port: Option<u16>,
+    /// Host port of the bundled Redis service (defaults to 6379)
+    #[arg(long)]
+    redis_port: Option<u16>,
+    /// Serve CKAN on all interfaces (0.0.0.0) instead of 127.0.0.1, e.g. to reach it from outside a VM or WSL
+    #[arg(long)]
+    bind_all: bool,
+    /// Generate random database passwords, sysadmin password and session secrets instead of the development defaults
+    #[arg(long)]
+    secure: bool,
+    /// Name of the CKAN instance to install or manage, each with its own paths, services and ports
+    #[arg(long, global = true, default_value = DEFAULT_INSTANCE)]
+    instance: String,
+    /// Path to a JSON config file, such as the /etc/ckan/default/ckan-devstaller.json written by a previous installation
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// Reproduce the exact installation recorded in a ckan-devstaller.lock.json lockfile
+    #[arg(long)]
+    locked: Option<PathBuf>,
     #[command(subcommand)]
     command: Option<Commands>,
 }
@@ -47,23 +158,142 @@ struct Args {
 enum Commands {
     /// Attempt to uninstall CKAN and related ckan-devstaller installation files
     Uninstall {},
+    /// Run CKAN along with the background processes of installed extensions, such as the ckanext-harvest consumers
+    Run {},
+    /// Verify that the CKAN instance is running and responding
+    Verify {
+        /// Also push a CSV file through DataPusher+ into the DataStore and query it back
+        #[arg(long)]
+        deep: bool,
+        /// Sysadmin username used for the deep verification (defaults to the sysadmin of the installation)
+        #[arg(long)]
+        sysadmin: Option<String>,
+    },
+    /// Load sample organizations, groups, datasets and users into CKAN
+    Seed {
+        /// JSON fixture file or directory containing a seed.json file (defaults to the bundled fixtures)
+        fixtures: Option<PathBuf>,
+        /// Sysadmin username used to create the sample data (defaults to the sysadmin of the installation)
+        #[arg(long)]
+        sysadmin: Option<String>,
+    },
+    /// Back up or restore the CKAN and DataStore databases and file storage
+    Db {
+        #[command(subcommand)]
+        command: DbCommands,
+    },
+    /// Export the whole environment to an archive or recreate it from one
+    Snapshot {
+        #[command(subcommand)]
+        command: SnapshotCommands,
+    },
+    /// Upgrade the installed CKAN to a newer version in place
+    Upgrade {
+        /// CKAN version to upgrade to
+        #[arg(long)]
+        ckan_version: String,
+        /// Upgrade even if an installed extension is known to be incompatible
+        #[arg(long)]
+        force: bool,
+    },
+    /// Manage the CKAN extensions of an existing installation
+    Extensions {
+        #[command(subcommand)]
+        command: ExtensionsCommands,
+    },
+    /// Show the passwords and secrets generated by --secure
+    Secrets {
+        #[command(subcommand)]
+        command: SecretsCommands,
+    },
+    /// List or remove the CKAN instances installed side by side
+    Instances {
+        #[command(subcommand)]
+        command: InstancesCommands,
+    },
+    /// Show the supported CKAN versions
+    Versions {
+        #[command(subcommand)]
+        command: VersionsCommands,
+    },
 }
 
-#[derive(Clone)]
-struct Sysadmin {
-    username: String,
-    password: String,
-    email: String,
+#[derive(Subcommand)]
+enum VersionsCommands {
+    /// Print the supported CKAN versions with their Python range, Solr image and extension refs
+    List,
+}
+
+#[derive(Subcommand)]
+enum SecretsCommands {
+    /// Print the passwords and secrets of the instance
+    Show,
+}
+
+#[derive(Subcommand)]
+enum InstancesCommands {
+    /// List the installed instances with their CKAN version, URL and services
+    List,
+    /// Stop the services of an instance and delete its virtual environment, config, file storage and data
+    Remove { name: String },
 }
 
-struct Config {
-    ssh: bool,
-    ckan_version: String,
-    sysadmin: Sysadmin,
-    extension_datastore: bool,
-    extension_ckanext_scheming: bool,
-    extension_datapusher_plus: bool,
-    druf_mode: bool,
+#[derive(Subcommand)]
+enum ExtensionsCommands {
+    /// Move installed extensions to a newer ref, reinstall their requirements and run their migrations
+    Update {
+        /// Extension to update (defaults to every installed extension)
+        name: Option<String>,
+        /// Branch, tag or commit to update to (defaults to the latest commit of the default branch)
+        #[arg(long = "ref")]
+        git_ref: Option<String>,
+    },
+    /// Install an extension into the existing installation
+    Add {
+        /// Name of a supported extension or a git URL
+        name: String,
+        /// Branch, tag or commit to install
+        #[arg(long = "ref")]
+        git_ref: Option<String>,
+        /// Plugins to add to ckan.plugins when installing from a git URL, separated by spaces
+        #[arg(long, value_parser, num_args = 1.., value_delimiter = ' ')]
+        plugins: Option<Vec<String>>,
+        /// Sysadmin username used to create API tokens for the extension (defaults to the sysadmin of the installation)
+        #[arg(long)]
+        sysadmin: Option<String>,
+    },
+    /// Uninstall an extension and revert its changes to the installation
+    Remove {
+        /// Name of a supported extension or of its directory in /usr/lib/ckan/default/src
+        name: String,
+        /// Plugins to remove from ckan.plugins when removing an extension installed from a git URL, separated by spaces
+        #[arg(long, value_parser, num_args = 1.., value_delimiter = ' ')]
+        plugins: Option<Vec<String>>,
+    },
+}
+
+#[derive(Subcommand)]
+enum DbCommands {
+    /// Dump the CKAN and DataStore databases and the file storage in /var/lib/ckan/<instance> into a timestamped archive
+    Backup {
+        /// Name prefixed to the backup's timestamp
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// Restore a backup by name (latest matching backup) or path
+    Restore { name: String },
+}
+
+#[derive(Subcommand)]
+enum SnapshotCommands {
+    /// Bundle the config, ckan.ini, installed repository refs, databases and file storage into one archive
+    Export {
+        /// Path of the snapshot archive (defaults to ~/ckan-devstaller-snapshot-<timestamp>.tar.gz)
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Recreate the environment of a snapshot on this machine and restore its data
+    Import { archive: PathBuf },
 }
 
 fn main() -> Result<()> {
@@ -71,8 +301,14 @@ fn main() -> Result<()> {
         .homepage("https://dathere.com")
         .support("- Create a support ticket at https://support.dathere.com or report an issue at https://github.com/dathere/ckan-devstaller"));
 
+    // Errors may include the output of commands, so hide any secrets they contain
+    run_cli(Args::parse()).map_err(|e| anyhow!(secrets::redact(&format!("{e:#}"))))
+}
+
+fn run_cli(args: Args) -> Result<()> {
     // Set up default config
-    let args = Args::parse();
+    instance::set_current(Instance::new(&args.instance)?)?;
+    let venv_dir = instance::current().venv_dir();
     let sh = Shell::new()?;
     let username = cmd!(sh, "whoami").read()?;
 
@@ -81,40 +317,215 @@ fn main() -> Result<()> {
             "Are you sure you want to uninstall CKAN and related files from ckan-devstaller?",
         )
         .with_help_message(
-            r#"The following commands are ran when attempting the uninstall:
+            r#"The following commands are ran for every instance when attempting the uninstall:
+sudo docker compose -p ckan-devstaller-project down --volumes (or podman-compose)
 sudo rm -rf /usr/lib/ckan
 sudo rm -rf /etc/ckan
 cd ~/
 rm -rf qsv*
-rm -rf README ckan-compose ahoy dpp_default_config.ini get-docker.sh permissions.sql"#,
+rm -rf README ckan-devstaller-compose* dpp_default_config.ini get-docker.sh permissions.sql"#,
         )
         .prompt()?;
         if uninstall_confirmation {
+            let mut instances = instance::installed()?;
+            if !instances.iter().any(Instance::is_default) {
+                instances.push(Instance::new(DEFAULT_INSTANCE)?);
+            }
+            for instance in instances {
+                instance::remove_services(&sh, &instance, &username)?;
+            }
             cmd!(sh, "sudo rm -rf /usr/lib/ckan").run()?;
             cmd!(sh, "sudo rm -rf /etc/ckan").run()?;
             sh.change_dir(format!("/home/{username}"));
             cmd!(sh, "rm -rf qsv*").run()?;
-            cmd!(sh, "rm -rf README ckan-compose ahoy dpp_default_config.ini get-docker.sh permissions.sql").run()?;
+            cmd!(sh, "rm -rf README ckan-devstaller-compose* dpp_default_config.ini get-docker.sh permissions.sql").run()?;
         } else {
             println!("Cancelling command.");
         }
         return Ok(());
     }
 
+    if let Some(Commands::Instances { command }) = &args.command {
+        return match command {
+            InstancesCommands::List => instance::list(),
+            InstancesCommands::Remove { name } => {
+                instance::remove(&sh, &username, name, args.skip_interactive)
+            }
+        };
+    }
+
+    if let Some(Commands::Versions {
+        command: VersionsCommands::List,
+    }) = &args.command
+    {
+        versions::list();
+        return Ok(());
+    }
+
+    if let Some(Commands::Secrets {
+        command: SecretsCommands::Show,
+    }) = &args.command
+    {
+        return secrets::show();
+    }
+
+    if matches!(&args.command, Some(Commands::Run {})) {
+        return run::run(&sh);
+    }
+
+    if let Some(Commands::Verify { deep, sysadmin }) = &args.command {
+        let venv_path = PathBuf::from_str(&venv_dir)?;
+        let _venv = VirtualEnv::with_path(&sh, &venv_path)?;
+        let sysadmin_username = sysadmin_username(sysadmin, &username)?;
+        return verify::verify(&sh, *deep, sysadmin_username);
+    }
+
+    if let Some(Commands::Seed { fixtures, sysadmin }) = &args.command {
+        let venv_path = PathBuf::from_str(&venv_dir)?;
+        let _venv = VirtualEnv::with_path(&sh, &venv_path)?;
+        let sysadmin_username = sysadmin_username(sysadmin, &username)?;
+        return seed::step_seed_data("1.".to_string(), &sh, fixtures.clone(), &sysadmin_username);
+    }
+
+    if let Some(Commands::Db { command }) = &args.command {
+        let venv_path = PathBuf::from_str(&venv_dir)?;
+        let _venv = VirtualEnv::with_path(&sh, &venv_path)?;
+        match command {
+            DbCommands::Backup { name } => {
+                db::backup(&sh, &username, name.clone())?;
+            }
+            DbCommands::Restore { name } => {
+                db::restore(&sh, &username, name, args.skip_interactive)?;
+            }
+        }
+        return Ok(());
+    }
+
+    if let Some(Commands::Snapshot { command }) = &args.command {
+        match command {
+            SnapshotCommands::Export { output } => {
+                let venv_path = PathBuf::from_str(&venv_dir)?;
+                let _venv = VirtualEnv::with_path(&sh, &venv_path)?;
+                snapshot::export(&sh, &username, output.clone())?;
+            }
+            SnapshotCommands::Import { archive } => {
+                snapshot::import(&sh, &username, archive)?;
+            }
+        }
+        return Ok(());
+    }
+
+    if let Some(Commands::Upgrade {
+        ckan_version,
+        force,
+    }) = &args.command
+    {
+        let venv_path = PathBuf::from_str(&venv_dir)?;
+        let _venv = VirtualEnv::with_path(&sh, &venv_path)?;
+        return upgrade::upgrade(&sh, &username, ckan_version, *force);
+    }
+
+    if let Some(Commands::Extensions { command }) = &args.command {
+        let venv_path = PathBuf::from_str(&venv_dir)?;
+        let _venv = VirtualEnv::with_path(&sh, &venv_path)?;
+        match command {
+            ExtensionsCommands::Update { name, git_ref } => {
+                extensions::update_extensions(&sh, name.as_deref(), git_ref.as_deref())?;
+            }
+            ExtensionsCommands::Add {
+                name,
+                git_ref,
+                plugins,
+                sysadmin,
+            } => {
+                let sysadmin_username = sysadmin_username(sysadmin, &username)?;
+                extensions::add_extension(
+                    &sh,
+                    &username,
+                    &sysadmin_username,
+                    name,
+                    git_ref.as_deref(),
+                    plugins.as_deref().unwrap_or_default(),
+                )?;
+                extensions::sync_saved_config()?;
+            }
+            ExtensionsCommands::Remove { name, plugins } => {
+                extensions::remove_extension(
+                    &sh,
+                    name,
+                    plugins.as_deref().unwrap_or_default(),
+                    args.skip_interactive,
+                )?;
+                extensions::sync_saved_config()?;
+            }
+        }
+        return Ok(());
+    }
+
     let default_sysadmin = Sysadmin {
         username: username.clone(),
         password: "password".to_string(),
         email: format!("{username}@localhost"),
     };
+    // Without explicit ports, other instances get the next ports that are not in use
+    let (ckan_port, service_ports) = if args.ckan_port.is_none()
+        && args.postgres_port.is_none()
+        && args.solr_port.is_none()
+        && args.redis_port.is_none()
+    {
+        instance::free_ports(
+            instance::current(),
+            DEFAULT_CKAN_PORT,
+            ServicePorts::default(),
+        )?
+    } else {
+        let default_ports = ServicePorts::default();
+        (
+            args.ckan_port.unwrap_or(DEFAULT_CKAN_PORT),
+            ServicePorts {
+                postgres: args.postgres_port.unwrap_or(default_ports.postgres),
+                solr: args.solr_port.unwrap_or(default_ports.solr),
+                redis: args.redis_port.unwrap_or(default_ports.redis),
+            },
+        )
+    };
+    let explicit_fields = explicit_fields(&args);
+    let services = match args.services.unwrap_or(ServicesKind::Compose) {
+        ServicesKind::Compose => Services::Compose {
+            runtime: args
+                .container_runtime
+                .unwrap_or_else(|| ContainerRuntime::detect(&sh)),
+            ports: service_ports,
+        },
+        ServicesKind::External => Services::External(ExternalServices {
+            postgres_url: args.postgres_url.clone().unwrap_or_default(),
+            datastore_write_url: args.datastore_write_url.clone(),
+            datastore_read_url: args.datastore_read_url.clone(),
+            solr_url: args.solr_url.clone().unwrap_or_default(),
+            redis_url: args.redis_url.clone().unwrap_or_default(),
+        }),
+    };
+    let ckan_source = match (&args.ckan_source, args.ckan_pr, &args.ckan_path) {
+        (Some(source), _, _) => CkanSource::git(source),
+        (_, Some(number), _) => CkanSource::Pr { number },
+        (_, _, Some(path)) => CkanSource::path(path)?,
+        _ => CkanSource::Release,
+    };
     let config = Config {
+        services,
+        ckan_port,
+        site_url: args.site_url,
+        bind_all: args.bind_all,
         ssh: args
             .features
+            .clone()
             .is_some_and(|features| features.contains(&"enable-ssh".to_string())),
-        ckan_version: if args.ckan_version.is_some() {
-            args.ckan_version.unwrap()
-        } else {
-            "2.11.5".to_string()
-        },
+        ckan_version: args
+            .ckan_version
+            .unwrap_or(versions::default_version().version.clone()),
+        ckan_source,
+        installer: args.installer.unwrap_or(Installer::Uv),
+        python: args.python,
         sysadmin: default_sysadmin.clone(),
         extension_datastore: args
             .extensions
@@ -126,8 +537,40 @@ rm -rf README ckan-compose ahoy dpp_default_config.ini get-docker.sh permissions
             .is_some_and(|extensions| extensions.contains(&"ckanext-scheming".to_string())),
         extension_datapusher_plus: args
             .extensions
+            .clone()
             .is_some_and(|extensions| extensions.contains(&"DataPusher+".to_string())),
+        extension_xloader: args
+            .extensions
+            .clone()
+            .is_some_and(|extensions| extensions.contains(&"XLoader".to_string())),
+        extension_ckanext_harvest: args
+            .extensions
+            .clone()
+            .is_some_and(|extensions| extensions.contains(&"ckanext-harvest".to_string())),
+        extension_ckanext_dcat: args
+            .extensions
+            .clone()
+            .is_some_and(|extensions| extensions.contains(&"ckanext-dcat".to_string())),
+        extension_ckanext_spatial: args
+            .extensions
+            .is_some_and(|extensions| extensions.contains(&"ckanext-spatial".to_string())),
         druf_mode: false,
+        dpp_ref: args.dpp_ref,
+        qsv_version: args.qsv_version,
+        datapusher_plus: match &args.dpp_profile {
+            Some(profile) => DataPusherPlusSettings::profile(profile)?,
+            None => DataPusherPlusSettings::default(),
+        },
+        dpp_profile: args.dpp_profile,
+        seed_data: args
+            .features
+            .is_some_and(|features| features.contains(&"seed-data".to_string())),
+        secure: args.secure,
+    };
+
+    let config = match &args.config {
+        Some(config_path) => Config::load(config_path, &config, &explicit_fields)?,
+        None => config,
     };
 
     steps::step_intro();
@@ -137,8 +580,57 @@ rm -rf README ckan-compose ahoy dpp_default_config.ini get-docker.sh permissions
     if config.ssh {
         default_config_text.push_str("\n- Install openssh-server to enable SSH access");
     }
-    default_config_text.push_str("\n- Install ckan-compose (https://github.com/tino097/ckan-compose/tree/ckan-devstaller) which sets up the CKAN backend (PostgreSQL, SOLR, Redis)");
-    default_config_text.push_str(format!("\n- Install CKAN v{}", config.ckan_version).as_str());
+    match &config.services {
+        Services::Compose { runtime, ports } => default_config_text.push_str(
+            format!(
+                "\n- Start the CKAN backend (PostgreSQL on port {}, Solr on port {}, Redis on port {}) with {}",
+                ports.postgres,
+                ports.solr,
+                ports.redis,
+                runtime.name()
+            )
+            .as_str(),
+        ),
+        Services::External(external) => default_config_text.push_str(
+            format!(
+                "\n- Use the existing PostgreSQL ({}), Solr ({}) and Redis ({}) servers",
+                external.postgres_url, external.solr_url, external.redis_url
+            )
+            .as_str(),
+        ),
+    }
+    if config.ckan_source.is_release() {
+        default_config_text.push_str(format!("\n- Install CKAN v{}", config.ckan_version).as_str());
+    } else {
+        default_config_text.push_str(
+            format!(
+                "\n- Install CKAN from {} as an editable install, with the services of CKAN v{}",
+                config.ckan_source, config.ckan_version
+            )
+            .as_str(),
+        );
+    }
+    match config.python_version() {
+        Some(python) => default_config_text.push_str(
+            format!("\n- Create the virtual environment with Python {python} using uv").as_str(),
+        ),
+        None => default_config_text
+            .push_str("\n- Create the virtual environment with the system python3 using pip"),
+    }
+    default_config_text.push_str(
+        format!(
+            "\n- Serve CKAN on {}:{} with the site URL {}",
+            config.bind_address(),
+            config.ckan_port,
+            config.site_url()
+        )
+        .as_str(),
+    );
+    if config.secure {
+        default_config_text.push_str(
+            "\n- Generate random database passwords, sysadmin password and session secrets",
+        );
+    }
     if config.extension_datastore {
         default_config_text.push_str("\n- Install the DataStore extension");
     }
@@ -146,9 +638,37 @@ rm -rf README ckan-compose ahoy dpp_default_config.ini get-docker.sh permissions
         default_config_text.push_str("\n- Install the ckanext-scheming extension");
     }
     if config.extension_datapusher_plus {
-        default_config_text.push_str("\n- Install the DataPusher+ extension");
+        default_config_text.push_str(
+            format!(
+                "\n- Install the DataPusher+ extension ({})",
+                config
+                    .dpp_ref
+                    .as_deref()
+                    .unwrap_or(DEFAULT_DATAPUSHER_PLUS_REF)
+            )
+            .as_str(),
+        );
         default_config_text.push_str("\n- Disable DRUF mode for DataPusher+");
     }
+    if config.extension_xloader {
+        default_config_text.push_str("\n- Install the XLoader extension");
+    }
+    if config.extension_ckanext_harvest {
+        default_config_text.push_str(
+            "\n- Install the ckanext-harvest extension and run its gather and fetch consumers",
+        );
+    }
+    if config.extension_ckanext_dcat {
+        default_config_text
+            .push_str("\n- Install the ckanext-dcat extension with its RDF endpoints");
+    }
+    if config.extension_ckanext_spatial {
+        default_config_text
+            .push_str("\n- Install the ckanext-spatial extension and enable PostGIS");
+    }
+    if config.seed_data {
+        default_config_text.push_str("\n- Seed sample organizations, groups, datasets and users");
+    }
     println!("{default_config_text}");
     let answer_customize = if args.skip_interactive {
         false
@@ -177,6 +697,22 @@ rm -rf README ckan-compose ahoy dpp_default_config.ini get-docker.sh permissions
         } else {
             false
         };
+        let (answer_dpp_profile, answer_datapusher_plus) = if answer_extension_datapusher_plus {
+            question_datapusher_plus_settings(
+                config.dpp_profile.clone(),
+                config.datapusher_plus.clone(),
+            )?
+        } else {
+            (config.dpp_profile.clone(), config.datapusher_plus.clone())
+        };
+        let answer_extension_xloader = if answer_extension_datapusher_plus {
+            false
+        } else {
+            Confirm::new("Would you like to install the XLoader extension instead?")
+                .with_default(false)
+                .with_help_message("XLoader cannot be installed alongside DataPusher+")
+                .prompt()?
+        };
         Config {
             ssh: answer_ssh,
             ckan_version: answer_ckan_version,
@@ -184,12 +720,23 @@ rm -rf README ckan-compose ahoy dpp_default_config.ini get-docker.sh permissions
             extension_datastore: true,
             extension_ckanext_scheming: true,
             extension_datapusher_plus: answer_extension_datapusher_plus,
+            extension_xloader: answer_extension_xloader,
             druf_mode: answer_druf_mode,
+            dpp_profile: answer_dpp_profile,
+            datapusher_plus: answer_datapusher_plus,
+            ..config
         }
     } else {
         config
     };
 
+    // Validated after the questions, so that answers are checked against the options, e.g. --python
+    // against the CKAN version chosen interactively
+    validation::validate_config(&config)?;
+    extensions::check_conflicts(&config.extension_names())?;
+    extensions::check_dependencies(&config.extension_names())?;
+    instance::check_ports(instance::current(), &config)?;
+
     let begin_installation = if args.skip_interactive {
         true
     } else {
@@ -197,100 +744,323 @@ rm -rf README ckan-compose ahoy dpp_default_config.ini get-docker.sh permissions
     };
 
     if begin_installation {
-        println!("\n{}", important_text("Starting installation..."));
-        // Run sudo apt update and sudo apt upgrade
-        step_package_updates("1.".to_string(), &sh)?;
-
-        // Install curl
-        step_install_curl("2.".to_string(), &sh)?;
-        // If user wants SSH capability, install openssh-server
-        if config.ssh {
-            step_install_openssh("2.".to_string(), &sh)?;
+        let lockfile = match &args.locked {
+            Some(lockfile_path) => Some(Lockfile::load(lockfile_path)?),
+            None => None,
+        };
+        install(&sh, &config, &username, args.skip_run, lockfile.as_ref())?;
+    } else {
+        println!("Cancelling installation.");
+    }
+
+    Ok(())
+}
+
+/// Sysadmin given with --sysadmin, otherwise the sysadmin of the saved config, falling back to the
+/// current user for installations without one.
+fn sysadmin_username(sysadmin: &Option<String>, username: &str) -> Result<String> {
+    if let Some(sysadmin) = sysadmin {
+        return Ok(sysadmin.clone());
+    }
+    Ok(Config::load_saved()?.map_or(username.to_string(), |config| config.sysadmin.username))
+}
+
+/// Config fields set by CLI options that were passed explicitly, which take precedence over a
+/// --config file.
+fn explicit_fields(args: &Args) -> Vec<&'static [&'static str]> {
+    let mut fields: Vec<&'static [&'static str]> = Vec::new();
+    let mut add = |passed: bool, paths: &[&'static [&'static str]]| {
+        if passed {
+            fields.extend_from_slice(paths);
         }
+    };
+    add(args.ckan_version.is_some(), &[&["ckan_version"]]);
+    add(
+        args.ckan_source.is_some() || args.ckan_pr.is_some() || args.ckan_path.is_some(),
+        &[&["ckan_source"]],
+    );
+    add(args.installer.is_some(), &[&["installer"]]);
+    add(args.python.is_some(), &[&["python"]]);
+    add(
+        args.extensions.is_some(),
+        &[
+            &["extension_datastore"],
+            &["extension_ckanext_scheming"],
+            &["extension_datapusher_plus"],
+            &["extension_xloader"],
+            &["extension_ckanext_harvest"],
+            &["extension_ckanext_dcat"],
+            &["extension_ckanext_spatial"],
+        ],
+    );
+    add(args.features.is_some(), &[&["ssh"], &["seed_data"]]);
+    add(args.dpp_ref.is_some(), &[&["dpp_ref"]]);
+    add(args.qsv_version.is_some(), &[&["qsv_version"]]);
+    add(
+        args.dpp_profile.is_some(),
+        &[&["dpp_profile"], &["datapusher_plus"]],
+    );
+    add(args.services.is_some(), &[&["services", "type"]]);
+    add(
+        args.container_runtime.is_some(),
+        &[&["services", "runtime"]],
+    );
+    add(
+        args.postgres_port.is_some(),
+        &[&["services", "ports", "postgres"]],
+    );
+    add(args.solr_port.is_some(), &[&["services", "ports", "solr"]]);
+    add(
+        args.redis_port.is_some(),
+        &[&["services", "ports", "redis"]],
+    );
+    add(
+        args.postgres_url.is_some(),
+        &[&["services", "postgres_url"]],
+    );
+    add(
+        args.datastore_write_url.is_some(),
+        &[&["services", "datastore_write_url"]],
+    );
+    add(
+        args.datastore_read_url.is_some(),
+        &[&["services", "datastore_read_url"]],
+    );
+    add(args.solr_url.is_some(), &[&["services", "solr_url"]]);
+    add(args.redis_url.is_some(), &[&["services", "redis_url"]]);
+    add(args.ckan_port.is_some(), &[&["ckan_port"]]);
+    add(args.site_url.is_some(), &[&["site_url"]]);
+    add(args.bind_all, &[&["bind_all"]]);
+    add(args.secure, &[&["secure"]]);
+    fields
+}
 
-        // Install docker CLI if user does not have it installed
-        step_install_docker("3.".to_string(), &sh, username.clone())?;
+/// Install CKAN, its backend services and the configured extensions and features.
+///
+/// When a lockfile is given, repositories, tools and Python packages are installed at the
+/// versions it records.
+pub fn install(
+    sh: &Shell,
+    config: &Config,
+    username: &str,
+    skip_run: bool,
+    lockfile: Option<&Lockfile>,
+) -> Result<()> {
+    let ckan_ini = instance::current().ini_path();
+    let src_dir = instance::current().src_dir();
+    let venv_dir = instance::current().venv_dir();
+    println!("\n{}", important_text("Starting installation..."));
+    // Run sudo apt update and sudo apt upgrade
+    step_package_updates("1.".to_string(), sh)?;
 
-        step_install_ahoy("4.".to_string(), &sh, username.clone())?;
+    // Install curl
+    step_install_curl("2.".to_string(), sh)?;
+    // If user wants SSH capability, install openssh-server
+    if config.ssh {
+        step_install_openssh("2.".to_string(), sh)?;
+    }
 
-        step_install_and_run_ckan_compose("5.".to_string(), &sh, username.clone())?;
+    let secrets = if config.secure {
+        Some(secrets::step_generate_secrets(
+            "3.".to_string(),
+            sh,
+            username,
+        )?)
+    } else {
+        None
+    };
 
-        println!(
-            "\n{} Installing CKAN {}...",
-            step_text("6."),
-            config.ckan_version
-        );
-        cmd!(sh, "sudo apt install python3-dev libpq-dev python3-pip python3-venv git-core redis-server -y").run()?;
-        cmd!(sh, "sudo mkdir -p /usr/lib/ckan/default").run()?;
-        cmd!(sh, "sudo chown {username} /usr/lib/ckan/default").run()?;
-        let venv_path = PathBuf::from_str("/usr/lib/ckan/default")?;
-        let venv = VirtualEnv::with_path(&sh, &venv_path)?;
-        venv.pip_upgrade("pip")?;
-        venv.pip_install(
-            format!(
-                "ckan[requirements]@git+https://github.com/ckan/ckan.git@ckan-{}",
-                config.ckan_version
-            )
-            .as_str(),
+    // External services are already running, so a container runtime and the bundled services are
+    // not needed
+    if let Services::Compose { runtime, ports } = config.services {
+        match runtime {
+            // Install docker CLI if user does not have it installed
+            ContainerRuntime::Docker => {
+                step_install_docker("3.".to_string(), sh, username.to_string())?
+            }
+            ContainerRuntime::Podman | ContainerRuntime::PodmanRootless => {
+                step_install_podman("3.".to_string(), sh)?
+            }
+        }
+        step_start_compose_services(
+            "4.".to_string(),
+            sh,
+            username,
+            config,
+            runtime,
+            ports,
+            lockfile,
         )?;
-        cmd!(sh, "sudo mkdir -p /etc/ckan/default").run()?;
-        cmd!(sh, "sudo chown -R {username} /etc/ckan/").run()?;
+    }
+
+    println!(
+        "\n{} Installing CKAN {}...",
+        step_text("5."),
+        config.ckan_version
+    );
+    cmd!(
+        sh,
+        "sudo apt install python3-dev libpq-dev python3-pip python3-venv git-core redis-server -y"
+    )
+    .run()?;
+    cmd!(sh, "sudo mkdir -p {venv_dir}").run()?;
+    cmd!(sh, "sudo chown {username} {venv_dir}").run()?;
+    // A virtual environment created by uv is reused by VirtualEnv, which otherwise creates one
+    // with the system python3
+    if let Some(python) = config.python_version() {
+        python::step_install_uv("5.".to_string(), sh, username)?;
+        python::create_uv_venv(sh, &python, &venv_dir)?;
+    }
+    let venv_path = PathBuf::from_str(&venv_dir)?;
+    let venv = VirtualEnv::with_path(sh, &venv_path)?;
+    if config.installer == Installer::Pip {
+        venv.pip_upgrade("pip")?;
+    }
+    // The checkout in src/ckan is the code running in the virtual environment, and also provides
+    // who.ini and resource_formats.json
+    ckan_source::step_install_ckan_source(
+        "5.".to_string(),
+        sh,
+        &config.ckan_source,
+        &config.ckan_version,
+        &src_dir,
+        lockfile,
+    )?;
+    let config_dir = instance::current().config_dir();
+    cmd!(sh, "sudo mkdir -p {config_dir}").run()?;
+    cmd!(sh, "sudo chown -R {username} /etc/ckan/").run()?;
+    sh.change_dir(format!("{src_dir}/ckan"));
+    python::pip_install(sh, &["setuptools==81.0.0"])?;
+    cmd!(sh, "ckan generate config {ckan_ini}").run()?;
+    step_configure_services("5.".to_string(), sh, &config.services, &config.site_url())?;
+    if let Some(secrets) = &secrets {
+        secrets::configure_ckan_secrets(secrets)?;
+    }
+    cmd!(sh, "ln -s {src_dir}/ckan/who.ini {config_dir}/who.ini").run()?;
+    sh.change_dir(format!("{src_dir}/ckan"));
+    python::pip_install(sh, &["flask-debugtoolbar==0.14.1"])?;
+    let storage_dir = instance::current().storage_dir();
+    cmd!(sh, "sudo mkdir -p {storage_dir}").run()?;
+    cmd!(sh, "sudo chown {username}.{username} {storage_dir}").run()?;
+    secrets::run_redacted(cmd!(sh, "ckan -c {ckan_ini} db init"))?;
+    let sysadmin_username = &config.sysadmin.username;
+    let sysadmin_password = match &secrets {
+        Some(secrets) => &secrets.sysadmin_password,
+        None => &config.sysadmin.password,
+    };
+    let sysadmin_email = &config.sysadmin.email;
+    // `ckan user add` prompts for the password and its confirmation when it is not an argument.
+    // The prompt reads from the terminal while there is one, so the command runs in a new session
+    // without a controlling terminal, where it reads the password from stdin instead.
+    secrets::run_redacted(
         cmd!(
             sh,
-            "git clone https://github.com/ckan/ckan.git /usr/lib/ckan/default/src/ckan"
+            "setsid -w ckan -c {ckan_ini} user add {sysadmin_username} email={sysadmin_email}"
         )
-        .run()?;
-        sh.change_dir("/usr/lib/ckan/default/src/ckan");
-        venv.pip_install("setuptools==81.0.0")?;
-        cmd!(sh, "ckan generate config /etc/ckan/default/ckan.ini").run()?;
-        cmd!(
+        .stdin(format!("{sysadmin_password}\n{sysadmin_password}\n")),
+    )?;
+    secrets::run_redacted(cmd!(
+        sh,
+        "ckan -c {ckan_ini} sysadmin add {sysadmin_username}"
+    ))?;
+    println!(
+        "{}",
+        success_text(format!("5. Installed CKAN {}.", config.ckan_version).as_str())
+    );
+
+    // Install extensions at the refs recorded in the lockfile, or those known to work with the
+    // CKAN version
+    let ckan_version = versions::find(&config.ckan_version);
+    let extension_ref = |name: &str, src_dir: &str| {
+        lockfile
+            .and_then(|lockfile| lockfile.repo_sha(src_dir))
+            .or(ckan_version.and_then(|version| version.extension_ref(name)))
+    };
+    if config.extension_datastore {
+        step_install_datastore_extension(
+            "6.".to_string(),
             sh,
-            "ln -s /usr/lib/ckan/default/src/ckan/who.ini /etc/ckan/default/who.ini"
-        )
-        .run()?;
-        sh.change_dir("/usr/lib/ckan/default/src/ckan");
-        venv.pip_install("flask-debugtoolbar==0.14.1")?;
-        sh.change_dir("/var/lib");
-        cmd!(sh, "sudo mkdir -p ckan/default").run()?;
-        cmd!(sh, "sudo chown {username}.{username} ckan/default").run()?;
-        cmd!(sh, "ckan -c /etc/ckan/default/ckan.ini db init").run()?;
-        let sysadmin_username = config.sysadmin.username;
-        let sysadmin_password = config.sysadmin.password;
-        let sysadmin_email = config.sysadmin.email;
-        cmd!(sh, "ckan -c /etc/ckan/default/ckan.ini user add {sysadmin_username} password={sysadmin_password} email={sysadmin_email}").run()?;
-        cmd!(
+            username.to_string(),
+            &config.services,
+        )?;
+    }
+    if config.extension_ckanext_scheming {
+        step_install_ckanext_scheming_extension(
+            "7.".to_string(),
             sh,
-            "ckan -c /etc/ckan/default/ckan.ini sysadmin add {sysadmin_username}"
-        )
-        .run()?;
-        println!(
-            "{}",
-            success_text(format!("6. Installed CKAN {}.", config.ckan_version).as_str())
-        );
+            extension_ref("ckanext-scheming", "ckanext-scheming"),
+        )?;
+    }
+    if config.extension_datapusher_plus {
+        let requested_ref = config
+            .dpp_ref
+            .as_deref()
+            .or(ckan_version.and_then(|version| version.extension_ref("DataPusher+")))
+            .unwrap_or(DEFAULT_DATAPUSHER_PLUS_REF);
+        let datapusher_plus_ref = lockfile
+            .and_then(|lockfile| lockfile.repo_sha("datapusher-plus"))
+            .unwrap_or(requested_ref);
+        let qsv_version = lockfile
+            .and_then(|lockfile| lockfile.qsv_version.as_deref())
+            .or(config.qsv_version.as_deref())
+            .unwrap_or(qsv_version_for(requested_ref));
+        step_install_datapusher_plus_extension(
+            "8.".to_string(),
+            sh,
+            sysadmin_username.to_string(),
+            username.to_string(),
+            config.druf_mode,
+            &config.datapusher_plus,
+            datapusher_plus_ref,
+            qsv_version,
+        )?;
+    }
+    if config.extension_xloader {
+        step_install_xloader_extension(
+            "8.".to_string(),
+            sh,
+            sysadmin_username.to_string(),
+            extension_ref("XLoader", "ckanext-xloader"),
+            &config.services,
+        )?;
+    }
+    if config.extension_ckanext_harvest {
+        step_install_harvest_extension(
+            "9.".to_string(),
+            sh,
+            extension_ref("ckanext-harvest", "ckanext-harvest"),
+            &config.services,
+        )?;
+    }
+    if config.extension_ckanext_dcat {
+        step_install_dcat_extension(
+            "10.".to_string(),
+            sh,
+            extension_ref("ckanext-dcat", "ckanext-dcat"),
+        )?;
+    }
+    if config.extension_ckanext_spatial {
+        step_install_spatial_extension(
+            "11.".to_string(),
+            sh,
+            username,
+            extension_ref("ckanext-spatial", "ckanext-spatial"),
+            &config.services,
+        )?;
+    }
+    if let Some(lockfile) = lockfile {
+        lockfile::step_install_locked_requirements("12.".to_string(), sh, lockfile)?;
+    }
+    // The seed step reads the CKAN port from the saved config
+    config.save(Path::new(&instance::current().config_path()))?;
+    if config.seed_data {
+        seed::step_seed_data("13.".to_string(), sh, None, sysadmin_username)?;
+    }
 
-        // Install extensions
-        if config.extension_datastore {
-            step_install_datastore_extension("7.".to_string(), &sh, username.clone())?;
-        }
-        if config.extension_ckanext_scheming {
-            step_install_ckanext_scheming_extension("8.".to_string(), &sh, username.clone())?;
-        }
-        if config.extension_datapusher_plus {
-            step_install_datapusher_plus_extension(
-                "9.".to_string(),
-                &sh,
-                sysadmin_username,
-                username.clone(),
-            )?;
-        }
+    lockfile::step_write_lockfile("14.".to_string(), sh)?;
 
-        if !args.skip_run {
-            println!("\n{}", success_text("Running CKAN instance..."));
-            cmd!(sh, "ckan -c /etc/ckan/default/ckan.ini run").run()?;
-        }
-    } else {
-        println!("Cancelling installation.");
+    if !skip_run {
+        run::run(sh)?;
     }
-
     Ok(())
 }
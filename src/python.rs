@@ -0,0 +1,143 @@
+use crate::{
+    instance,
+    steps::git_requirement,
+    styles::{highlighted_text, step_text, success_text},
+};
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use xshell::{Cmd, Shell, cmd};
+
+/// Python versions that uv can create the virtual environment with
+pub const PYTHON_VERSIONS: &[&str] = &["3.10", "3.11", "3.12"];
+
+/// Tool that creates the virtual environment and installs Python packages into it
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Installer {
+    /// uv, which also fetches the Python interpreter selected with --python
+    Uv,
+    /// venv and pip of the system python3, the default of configs saved before uv was supported
+    #[default]
+    Pip,
+}
+
+impl Installer {
+    /// Installer of the current instance's virtual environment, uv records its version in
+    /// pyvenv.cfg.
+    pub fn current() -> Installer {
+        let pyvenv_cfg = format!("{}/pyvenv.cfg", instance::current().venv_dir());
+        match std::fs::read_to_string(pyvenv_cfg) {
+            Ok(contents) if contents.lines().any(|line| line.starts_with("uv =")) => Installer::Uv,
+            _ => Installer::Pip,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Installer::Uv => "uv",
+            Installer::Pip => "pip",
+        }
+    }
+}
+
+/// `pip` of the current virtual environment, `uv pip` if it was created by uv.
+pub fn pip(sh: &Shell) -> Cmd<'_> {
+    match Installer::current() {
+        Installer::Uv => cmd!(sh, "uv pip"),
+        Installer::Pip => cmd!(sh, "pip"),
+    }
+}
+
+/// `pip install` into the current virtual environment.
+pub fn pip_install(sh: &Shell, args: &[&str]) -> Result<()> {
+    pip(sh).arg("install").args(args).run()?;
+    Ok(())
+}
+
+/// Uninstall a package from the current virtual environment, ignoring packages that are not
+/// installed.
+pub fn uninstall(sh: &Shell, package: &str) -> Result<()> {
+    match Installer::current() {
+        Installer::Uv => cmd!(sh, "uv pip uninstall {package}"),
+        Installer::Pip => cmd!(sh, "pip uninstall -y {package}"),
+    }
+    .ignore_status()
+    .run()?;
+    Ok(())
+}
+
+/// Editable install of a git repository, optionally at a branch, tag or commit, into
+/// `{src_dir}/{name}`.
+///
+/// pip clones the repository itself, uv only installs local directories in editable mode so the
+/// repository is cloned first.
+pub fn install_git_editable(
+    sh: &Shell,
+    name: &str,
+    url: &str,
+    git_ref: Option<&str>,
+) -> Result<()> {
+    let src_dir = instance::current().src_dir();
+    match Installer::current() {
+        Installer::Uv => {
+            let package_dir = format!("{src_dir}/{name}");
+            cmd!(sh, "git clone {url} {package_dir}").run()?;
+            if let Some(git_ref) = git_ref {
+                cmd!(sh, "git -C {package_dir} checkout {git_ref}").run()?;
+            }
+            cmd!(sh, "uv pip install -e {package_dir}").run()?;
+        }
+        Installer::Pip => {
+            sh.change_dir(&src_dir);
+            let requirement = git_requirement(name, url, git_ref);
+            cmd!(sh, "pip install -e {requirement}").run()?;
+        }
+    }
+    Ok(())
+}
+
+/// Install uv into /usr/local/bin, unless it is already installed.
+pub fn step_install_uv(step_prefix: String, sh: &Shell, username: &str) -> Result<()> {
+    println!(
+        "\n{} Installing {}...",
+        step_text(step_prefix.as_str()),
+        highlighted_text("uv")
+    );
+    if cmd!(sh, "uv --version")
+        .quiet()
+        .ignore_stderr()
+        .read()
+        .is_ok()
+    {
+        println!(
+            "{}",
+            success_text(format!("{step_prefix} uv is already installed.").as_str())
+        );
+        return Ok(());
+    }
+    sh.change_dir(format!("/home/{username}"));
+    cmd!(
+        sh,
+        "curl -LsSf https://astral.sh/uv/install.sh -o uv-install.sh"
+    )
+    .run()?;
+    cmd!(
+        sh,
+        "sudo env UV_UNMANAGED_INSTALL=/usr/local/bin sh uv-install.sh"
+    )
+    .run()?;
+    cmd!(sh, "rm uv-install.sh").run()?;
+    println!(
+        "{}",
+        success_text(format!("{step_prefix} Installed uv.").as_str())
+    );
+    Ok(())
+}
+
+/// Create the virtual environment with uv, which fetches the Python interpreter if needed. pip is
+/// seeded into it so that commands run inside the environment can still use it.
+pub fn create_uv_venv(sh: &Shell, python: &str, venv_dir: &str) -> Result<()> {
+    cmd!(sh, "uv venv --seed --python {python} {venv_dir}").run()?;
+    Ok(())
+}
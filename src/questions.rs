@@ -1,6 +1,14 @@
-use crate::{Sysadmin, styles::highlighted_text};
+use crate::{
+    config::Sysadmin,
+    datapusher_plus::{DataPusherPlusSettings, PROFILES},
+    styles::highlighted_text,
+    validation::{
+        validate_ckan_version, validate_email, validate_password, validate_username, validator,
+    },
+    versions,
+};
 use anyhow::Result;
-use inquire::{Confirm, Select, Text};
+use inquire::{Confirm, CustomType, Password, PasswordDisplayMode, Select, Text};
 
 pub fn question_ssh() -> Result<bool> {
     Ok(Confirm::new("Would you like to enable SSH? (optional)")
@@ -16,7 +24,11 @@ pub fn question_ssh() -> Result<bool> {
 }
 
 pub fn question_ckan_version() -> Result<String> {
-    let ckan_version_options: Vec<&str> = vec!["2.11.5", "2.10.10", "Other"];
+    let mut ckan_version_options: Vec<&str> = versions::catalog()
+        .iter()
+        .map(|version| version.version.as_str())
+        .collect();
+    ckan_version_options.push("Other");
     let answer_ckan_version = Select::new(
         "What CKAN version would you like to install? (optional)",
         ckan_version_options,
@@ -26,7 +38,8 @@ pub fn question_ckan_version() -> Result<String> {
     if answer_ckan_version == "Other" {
         Ok(
             Text::new("What CKAN version would you like to install? (optional)")
-                .with_default("2.11.5")
+                .with_default(&versions::default_version().version)
+                .with_validator(validator(validate_ckan_version))
                 .prompt()?,
         )
     } else {
@@ -41,13 +54,19 @@ pub fn question_sysadmin(username: String) -> Result<Sysadmin> {
     if configure_sysadmin {
         let username = Text::new("What should your sysadmin username be set to?")
             .with_default(username.clone().as_str())
+            .with_help_message("Lowercase alphanumeric characters, - and _")
+            .with_validator(validator(validate_username))
             .prompt()?;
-        let password = Text::new("What should your sysadmin password be set to?")
-            .with_default("password")
+        let password = Password::new("What should your sysadmin password be set to?")
+            .with_display_mode(PasswordDisplayMode::Masked)
+            .with_custom_confirmation_message("Please confirm the sysadmin password:")
+            .with_custom_confirmation_error_message("The passwords don't match.")
             .with_help_message("The password must be at least 8 characters long")
+            .with_validator(validator(validate_password))
             .prompt()?;
         let email = Text::new("What should your sysadmin email be set to?")
             .with_default(format!("{username}@localhost").as_str())
+            .with_validator(validator(validate_email))
             .prompt()?;
         Ok(Sysadmin {
             username,
@@ -62,3 +81,61 @@ pub fn question_sysadmin(username: String) -> Result<Sysadmin> {
         })
     }
 }
+
+pub fn question_datapusher_plus_settings(
+    profile: Option<String>,
+    settings: DataPusherPlusSettings,
+) -> Result<(Option<String>, DataPusherPlusSettings)> {
+    let customize_settings = Confirm::new("Would you like to customize the DataPusher+ settings?")
+        .with_default(false)
+        .prompt()?;
+    if !customize_settings {
+        return Ok((profile, settings));
+    }
+    let current_profile = profile.as_deref().unwrap_or("default");
+    let profile = Select::new("Which DataPusher+ profile would you like to start from?", PROFILES.to_vec())
+        .with_starting_cursor(PROFILES.iter().position(|name| *name == current_profile).unwrap_or(0))
+        .with_help_message("pii-screening screens uploads for personally identifiable information and large-files tunes DataPusher+ for slow downloads and big files")
+        .prompt()?;
+    // The given settings already start from the current profile, with the config file's overrides
+    let settings = if profile == current_profile {
+        settings
+    } else {
+        DataPusherPlusSettings::profile(profile)?
+    };
+    let preview_rows =
+        CustomType::<u64>::new("How many rows should be previewed? (0 loads every row)")
+            .with_default(settings.preview_rows)
+            .prompt()?;
+    let auto_index_threshold = CustomType::<u64>::new(
+        "Up to how many unique values should a column have to be automatically indexed?",
+    )
+    .with_default(settings.auto_index_threshold)
+    .prompt()?;
+    let pii_screening =
+        Confirm::new("Would you like to screen uploads for personally identifiable information?")
+            .with_default(settings.pii_screening)
+            .prompt()?;
+    let describegpt_api_key = Password::new("What API key should describeGPT use? (optional)")
+        .with_display_mode(PasswordDisplayMode::Masked)
+        .without_confirmation()
+        .with_help_message(
+            "Token for an OpenAI API compatible service, leave empty to keep the current one",
+        )
+        .prompt()?;
+    let describegpt_api_key = if describegpt_api_key.is_empty() {
+        settings.describegpt_api_key.clone()
+    } else {
+        describegpt_api_key
+    };
+    Ok((
+        Some(profile.to_string()),
+        DataPusherPlusSettings {
+            preview_rows,
+            auto_index_threshold,
+            pii_screening,
+            describegpt_api_key,
+            ..settings
+        },
+    ))
+}
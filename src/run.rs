@@ -0,0 +1,60 @@
+use crate::{
+    config::{Config, DEFAULT_CKAN_PORT},
+    extensions::find_extension,
+    instance,
+    styles::{important_text, success_text},
+};
+use anyhow::Result;
+use std::process::{Child, Command};
+use xshell::{Shell, cmd};
+
+/// Background processes that installed extensions need alongside CKAN, as `ckan` CLI arguments
+const EXTENSION_PROCESSES: &[(&str, &[&str])] = &[
+    ("ckanext-harvest", &["harvester", "gather-consumer"]),
+    ("ckanext-harvest", &["harvester", "fetch-consumer"]),
+];
+
+/// Host and port `ckan run` listens on, from the saved config of the current installation.
+pub fn listen_address() -> Result<(&'static str, String)> {
+    Ok(match Config::load_saved()? {
+        Some(config) => (config.bind_address(), config.ckan_port.to_string()),
+        None => ("127.0.0.1", DEFAULT_CKAN_PORT.to_string()),
+    })
+}
+
+/// Run CKAN along with the background processes of the installed extensions, stopping them when
+/// CKAN exits.
+pub fn run(sh: &Shell) -> Result<()> {
+    let ckan_bin = instance::current().ckan_bin();
+    let ckan_ini = instance::current().ini_path();
+    let mut processes: Vec<Child> = vec![];
+    for (extension_name, args) in EXTENSION_PROCESSES {
+        if !find_extension(extension_name)
+            .is_some_and(|extension| extension.is_installed().unwrap_or(false))
+        {
+            continue;
+        }
+        println!(
+            "{}",
+            important_text(format!("Starting ckan {}...", args.join(" ")).as_str())
+        );
+        processes.push(
+            Command::new(&ckan_bin)
+                .args(["-c", &ckan_ini])
+                .args(*args)
+                .spawn()?,
+        );
+    }
+    println!("\n{}", success_text("Running CKAN instance..."));
+    let (host, port) = listen_address()?;
+    let result = cmd!(
+        sh,
+        "{ckan_bin} -c {ckan_ini} run --host {host} --port {port}"
+    )
+    .run();
+    for mut process in processes {
+        process.kill().ok();
+        process.wait()?;
+    }
+    Ok(result?)
+}
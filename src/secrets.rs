@@ -0,0 +1,351 @@
+use crate::{
+    instance,
+    styles::{highlighted_text, step_text, success_text},
+};
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::OpenOptions,
+    io::{Read, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use xshell::{Cmd, Shell, cmd};
+
+/// Password of the bundled PostgreSQL users when the instance was not installed with --secure
+pub const DEFAULT_DATABASE_PASSWORD: &str = "pass";
+
+/// Secrets created while running, such as API tokens, that [`redact`] hides
+static KNOWN_SECRETS: Mutex<Vec<String>> = Mutex::new(vec![]);
+
+/// Random secrets of an instance installed with --secure
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Secrets {
+    /// Password of the ckan_default PostgreSQL user of the bundled services
+    pub postgres_password: String,
+    /// Password of the read-only datastore_default PostgreSQL user of the bundled services
+    pub datastore_password: String,
+    pub sysadmin_password: String,
+    /// Used for SECRET_KEY, beaker.session.secret and WTF_CSRF_SECRET_KEY
+    pub session_secret: String,
+    /// Used to sign and verify API tokens
+    pub jwt_secret: String,
+}
+
+impl Secrets {
+    pub fn generate() -> Result<Secrets> {
+        Ok(Secrets {
+            postgres_password: random_secret(24)?,
+            datastore_password: random_secret(24)?,
+            sysadmin_password: random_secret(16)?,
+            session_secret: random_secret(32)?,
+            jwt_secret: random_secret(32)?,
+        })
+    }
+
+    /// Load the secrets of the current instance, `None` if it was not installed with --secure.
+    pub fn load() -> Result<Option<Secrets>> {
+        let secrets_path = instance::current().secrets_path();
+        if !std::fs::exists(&secrets_path)? {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_str(&std::fs::read_to_string(
+            secrets_path,
+        )?)?))
+    }
+
+    /// Write the secrets to a file only readable by the current user.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(path)?;
+        file.write_all(serde_json::to_string_pretty(self)?.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// Password of the ckan_default PostgreSQL user of the bundled services.
+pub fn postgres_password() -> Result<String> {
+    Ok(
+        Secrets::load()?.map_or(DEFAULT_DATABASE_PASSWORD.to_string(), |secrets| {
+            secrets.postgres_password
+        }),
+    )
+}
+
+/// Password of the read-only datastore_default PostgreSQL user of the bundled services.
+pub fn datastore_password() -> Result<String> {
+    Ok(
+        Secrets::load()?.map_or(DEFAULT_DATABASE_PASSWORD.to_string(), |secrets| {
+            secrets.datastore_password
+        }),
+    )
+}
+
+/// Make [`redact`] hide a secret created while running, such as an API token.
+pub fn register(secret: &str) {
+    if let Ok(mut known_secrets) = KNOWN_SECRETS.lock() {
+        known_secrets.push(secret.to_string());
+    }
+}
+
+/// Replace the secrets of the current instance and those given to [`register`] in `text`.
+pub fn redact(text: &str) -> String {
+    let mut secrets = KNOWN_SECRETS
+        .lock()
+        .map(|known_secrets| known_secrets.clone())
+        .unwrap_or_default();
+    if let Ok(Some(instance_secrets)) = Secrets::load() {
+        secrets.extend([
+            instance_secrets.postgres_password,
+            instance_secrets.datastore_password,
+            instance_secrets.sysadmin_password,
+            instance_secrets.session_secret,
+            instance_secrets.jwt_secret,
+        ]);
+    }
+    secrets
+        .iter()
+        .filter(|secret| !secret.is_empty())
+        .fold(text.to_string(), |text, secret| {
+            text.replace(secret.as_str(), "********")
+        })
+}
+
+/// Run a command whose output may contain secrets. Its command line and output are printed once it
+/// exits, with the known secrets redacted, instead of being streamed to the terminal.
+pub fn run_redacted(cmd: Cmd<'_>) -> Result<()> {
+    let command_line = redact(&cmd.to_string());
+    eprintln!("$ {command_line}");
+    let output = cmd.quiet().ignore_status().output()?;
+    print!("{}", redact(&String::from_utf8_lossy(&output.stdout)));
+    eprint!("{}", redact(&String::from_utf8_lossy(&output.stderr)));
+    if !output.status.success() {
+        return Err(anyhow!(
+            "command exited with {}: {command_line}",
+            output.status
+        ));
+    }
+    Ok(())
+}
+
+/// Split the password out of a PostgreSQL connection URL so that it can be passed with the
+/// PGPASSWORD environment variable instead of the command line. The password is percent-decoded,
+/// as PGPASSWORD takes it verbatim.
+pub fn split_postgres_password(postgres_url: &str) -> (String, Option<String>) {
+    let Some((scheme, rest)) = postgres_url.split_once("://") else {
+        return (postgres_url.to_string(), None);
+    };
+    // Only the part before the path and query can hold credentials
+    let authority_end = rest.find(['/', '?']).unwrap_or(rest.len());
+    let Some(at) = rest[..authority_end].rfind('@') else {
+        return (postgres_url.to_string(), None);
+    };
+    let (user_info, host) = (&rest[..at], &rest[at + 1..]);
+    match user_info.split_once(':') {
+        Some((user, password)) => (
+            format!("{scheme}://{user}@{host}"),
+            Some(percent_decode(password)),
+        ),
+        None => (postgres_url.to_string(), None),
+    }
+}
+
+/// Decode the %XX escapes of a URL component such as a password, leaving invalid escapes as is.
+pub fn percent_decode(component: &str) -> String {
+    let bytes = component.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| component.get(i + 1..i + 3))
+            .flatten()
+            .filter(|hex| hex.chars().all(|c| c.is_ascii_hexdigit()))
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// A temporary file only readable by the current user, deleted when dropped. Used to pass secrets
+/// to commands without putting them on the command line.
+pub struct SecretFile {
+    pub path: PathBuf,
+}
+
+impl SecretFile {
+    pub fn new(contents: &str) -> Result<SecretFile> {
+        let path = PathBuf::from(format!(
+            "/tmp/ckan-devstaller-{}-{}",
+            std::process::id(),
+            random_secret(8)?
+        ));
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)?;
+        file.write_all(contents.as_bytes())?;
+        Ok(SecretFile { path })
+    }
+}
+
+impl Drop for SecretFile {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.path).ok();
+    }
+}
+
+/// Hex encoded random bytes from /dev/urandom.
+fn random_secret(bytes: usize) -> Result<String> {
+    let mut buffer = vec![0; bytes];
+    std::fs::File::open("/dev/urandom")?.read_exact(&mut buffer)?;
+    Ok(buffer.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+/// Generate the secrets of the current instance, keeping those of an earlier installation or
+/// imported snapshot.
+pub fn step_generate_secrets(step_prefix: String, sh: &Shell, username: &str) -> Result<Secrets> {
+    let secrets_path = instance::current().secrets_path();
+    println!(
+        "\n{} Generating random passwords and secrets in {}...",
+        step_text(step_prefix.as_str()),
+        secrets_path
+    );
+    let secrets = match Secrets::load()? {
+        Some(secrets) => secrets,
+        None => {
+            let config_dir = instance::current().config_dir();
+            cmd!(sh, "sudo mkdir -p {config_dir}").run()?;
+            cmd!(sh, "sudo chown -R {username} /etc/ckan/").run()?;
+            let secrets = Secrets::generate()?;
+            secrets.save(Path::new(&secrets_path))?;
+            secrets
+        }
+    };
+    println!(
+        "{}",
+        success_text(
+            format!(
+                "{step_prefix} Generated the secrets, print them with: ckan-devstaller secrets show"
+            )
+            .as_str()
+        )
+    );
+    Ok(secrets)
+}
+
+/// Set the session and API token secrets in ckan.ini.
+pub fn configure_ckan_secrets(secrets: &Secrets) -> Result<()> {
+    let ckan_ini = instance::current().ini_path();
+    let mut conf = ini::Ini::load_from_file(&ckan_ini)?;
+    let app_main_section = conf
+        .section_mut(Some("app:main"))
+        .ok_or_else(|| anyhow!("Missing [app:main] section in {ckan_ini}"))?;
+    app_main_section.insert("SECRET_KEY", &secrets.session_secret);
+    app_main_section.insert("beaker.session.secret", &secrets.session_secret);
+    app_main_section.insert("WTF_CSRF_SECRET_KEY", &secrets.session_secret);
+    let jwt_secret = format!("string:{}", secrets.jwt_secret);
+    app_main_section.insert("api_token.jwt.encode.secret", &jwt_secret);
+    app_main_section.insert("api_token.jwt.decode.secret", &jwt_secret);
+    conf.write_to_file(&ckan_ini)?;
+    Ok(())
+}
+
+/// Print the secrets of the current instance.
+pub fn show() -> Result<()> {
+    let instance = instance::current();
+    let secrets = Secrets::load()?.ok_or_else(|| {
+        anyhow!(
+            "The {} instance was not installed with --secure, it uses the password {DEFAULT_DATABASE_PASSWORD} for PostgreSQL",
+            instance.name
+        )
+    })?;
+    println!(
+        "Secrets of the {} instance:",
+        highlighted_text(&instance.name)
+    );
+    println!(
+        "- PostgreSQL ckan_default password: {}",
+        secrets.postgres_password
+    );
+    println!(
+        "- PostgreSQL datastore_default password: {}",
+        secrets.datastore_password
+    );
+    println!("- Sysadmin password: {}", secrets.sysadmin_password);
+    println!("- Session secret: {}", secrets.session_secret);
+    println!("- API token secret: {}", secrets.jwt_secret);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_postgres_password_moves_the_password_out_of_the_url() {
+        assert_eq!(
+            split_postgres_password("postgresql://ckan:pass@db:5432/ckan?sslmode=require"),
+            (
+                "postgresql://ckan@db:5432/ckan?sslmode=require".to_string(),
+                Some("pass".to_string())
+            )
+        );
+    }
+
+    #[test]
+    fn split_postgres_password_decodes_the_password() {
+        assert_eq!(
+            split_postgres_password("postgresql://ckan:p%40ss%2Fword@db/ckan"),
+            (
+                "postgresql://ckan@db/ckan".to_string(),
+                Some("p@ss/word".to_string())
+            )
+        );
+    }
+
+    #[test]
+    fn split_postgres_password_keeps_urls_without_a_password() {
+        for postgres_url in [
+            "postgresql://db/ckan",
+            "postgresql://ckan@db/ckan",
+            "postgresql://db/ckan?options=a@b",
+            "host=db dbname=ckan",
+        ] {
+            assert_eq!(
+                split_postgres_password(postgres_url),
+                (postgres_url.to_string(), None)
+            );
+        }
+    }
+
+    #[test]
+    fn run_redacted_redacts_the_failed_command() {
+        register("run-redacted-test-secret");
+        let sh = Shell::new().unwrap();
+        let error = run_redacted(cmd!(sh, "false run-redacted-test-secret"))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("false ********"));
+        assert!(!error.contains("run-redacted-test-secret"));
+    }
+
+    #[test]
+    fn percent_decode_leaves_invalid_escapes() {
+        assert_eq!(percent_decode("a%2Bb%zz%+1%"), "a+b%zz%+1%");
+    }
+}
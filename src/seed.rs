@@ -0,0 +1,152 @@
+use crate::{
+    ckan_api,
+    styles::{highlighted_text, step_text, success_text},
+};
+use anyhow::{Result, anyhow};
+use serde_json::{Value, json};
+use std::path::{Path, PathBuf};
+use xshell::Shell;
+
+/// Bundled fixture set, written to [`BUNDLED_FIXTURES_DIR`] before seeding.
+const BUNDLED_FIXTURES: &[(&str, &[u8])] = &[
+    ("seed.json", include_bytes!("../fixtures/seed/seed.json")),
+    (
+        "files/library-branches.csv",
+        include_bytes!("../fixtures/seed/files/library-branches.csv"),
+    ),
+    (
+        "files/library-visits.csv",
+        include_bytes!("../fixtures/seed/files/library-visits.csv"),
+    ),
+    (
+        "files/city-budget.xlsx",
+        include_bytes!("../fixtures/seed/files/city-budget.xlsx"),
+    ),
+    (
+        "files/parks.geojson",
+        include_bytes!("../fixtures/seed/files/parks.geojson"),
+    ),
+];
+const BUNDLED_FIXTURES_DIR: &str = "/tmp/ckan-devstaller-seed";
+
+/// Load a fixture set into CKAN through the CKAN API.
+///
+/// `fixtures` may be a JSON file or a directory containing a `seed.json` file. Resources with an
+/// `upload` key are uploaded from a path relative to the fixture file. When `fixtures` is `None`
+/// the bundled fixture set is used.
+pub fn step_seed_data(
+    step_prefix: String,
+    sh: &Shell,
+    fixtures: Option<PathBuf>,
+    sysadmin_username: &str,
+) -> Result<()> {
+    println!(
+        "\n{} Seeding sample organizations, groups, datasets and users...",
+        step_text(step_prefix.as_str()),
+    );
+    let fixtures_file = match fixtures {
+        Some(path) if path.is_dir() => path.join("seed.json"),
+        Some(path) => path,
+        None => {
+            for (name, contents) in BUNDLED_FIXTURES {
+                let path = Path::new(BUNDLED_FIXTURES_DIR).join(name);
+                std::fs::create_dir_all(path.parent().unwrap())?;
+                std::fs::write(path, contents)?;
+            }
+            Path::new(BUNDLED_FIXTURES_DIR).join("seed.json")
+        }
+    };
+    let fixtures_dir = fixtures_file
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    let fixtures: Value = serde_json::from_str(&std::fs::read_to_string(&fixtures_file)?)
+        .map_err(|e| anyhow!("Could not parse {}: {e}", fixtures_file.display()))?;
+
+    ckan_api::with_ckan_running(sh, || {
+        let api_token = ckan_api::create_api_token(sh, sysadmin_username, "devstaller-seed")?;
+        let result = seed(sh, &api_token, &fixtures, &fixtures_dir);
+        ckan_api::action(
+            sh,
+            Some(&api_token),
+            "api_token_revoke",
+            &json!({ "token": api_token }),
+        )?;
+        result
+    })?;
+    println!(
+        "{}",
+        success_text(
+            format!(
+                "{step_prefix} Seeded sample data from {}.",
+                fixtures_file.display()
+            )
+            .as_str()
+        )
+    );
+    Ok(())
+}
+
+fn seed(sh: &Shell, api_token: &str, fixtures: &Value, fixtures_dir: &Path) -> Result<()> {
+    for (key, show_action, create_action) in [
+        ("users", "user_show", "user_create"),
+        ("organizations", "organization_show", "organization_create"),
+        ("groups", "group_show", "group_create"),
+    ] {
+        for entity in fixtures[key].as_array().into_iter().flatten() {
+            create_if_missing(sh, api_token, show_action, create_action, entity)?;
+        }
+    }
+    for dataset in fixtures["datasets"].as_array().into_iter().flatten() {
+        let mut dataset = dataset.clone();
+        let resources = dataset
+            .as_object_mut()
+            .and_then(|dataset| dataset.remove("resources"))
+            .unwrap_or_default();
+        if !create_if_missing(sh, api_token, "package_show", "package_create", &dataset)? {
+            continue;
+        }
+        for resource in resources.as_array().into_iter().flatten() {
+            let mut resource = resource.clone();
+            resource["package_id"] = dataset["name"].clone();
+            let upload = resource
+                .as_object_mut()
+                .and_then(|resource| resource.remove("upload"));
+            match upload {
+                Some(Value::String(upload)) => {
+                    let upload_path = fixtures_dir.join(upload);
+                    ckan_api::upload_resource(
+                        sh,
+                        api_token,
+                        upload_path.to_string_lossy().as_ref(),
+                        &resource,
+                    )?;
+                }
+                _ => {
+                    ckan_api::action(sh, Some(api_token), "resource_create", &resource)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Create an entity unless one with the same name already exists, returning whether it was created.
+fn create_if_missing(
+    sh: &Shell,
+    api_token: &str,
+    show_action: &str,
+    create_action: &str,
+    entity: &Value,
+) -> Result<bool> {
+    let name = entity["name"]
+        .as_str()
+        .ok_or_else(|| anyhow!("Every fixture needs a name: {entity}"))?;
+    if ckan_api::action(sh, Some(api_token), show_action, &json!({ "id": name })).is_ok() {
+        println!("Skipping {}, it already exists.", highlighted_text(name));
+        return Ok(false);
+    }
+    ckan_api::action(sh, Some(api_token), create_action, entity)?;
+    println!("Created {}.", highlighted_text(name));
+    Ok(true)
+}
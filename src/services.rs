@@ -0,0 +1,666 @@
+use crate::{
+    config::Config,
+    container::ContainerRuntime,
+    instance,
+    lockfile::Lockfile,
+    secrets,
+    styles::{step_text, success_text},
+    versions,
+};
+use anyhow::{Result, anyhow};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, os::unix::fs::PermissionsExt, path::Path};
+use xshell::{Shell, cmd};
+
+const COMPOSE_FILE: &str = include_str!("../compose/docker-compose.yml");
+const POSTGRES_INIT_SCRIPT: &str = include_str!("../compose/postgres-init.sh");
+/// PostgreSQL image of the bundled services
+pub const POSTGRES_IMAGE: &str = "docker.io/library/postgres:16";
+/// PostgreSQL image with PostGIS, used instead of [`POSTGRES_IMAGE`] when ckanext-spatial is
+/// installed. Based on the same Debian postgres image, so it uses the existing data volume.
+pub const POSTGIS_IMAGE: &str = "docker.io/postgis/postgis:16-3.4";
+/// Redis image of the bundled services
+pub const REDIS_IMAGE: &str = "docker.io/library/redis:7";
+/// Bundled services and the .env variable selecting their image
+const SERVICE_IMAGE_VARS: &[(&str, &str)] = &[
+    ("postgres", "POSTGRES_IMAGE"),
+    ("solr", "CKAN_SOLR_IMAGE"),
+    ("redis", "REDIS_IMAGE"),
+];
+
+/// Where the PostgreSQL, Solr and Redis backends of CKAN come from
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum ServicesKind {
+    /// Start them with the bundled docker compose file
+    Compose,
+    /// Use existing servers given with --postgres-url, --solr-url and --redis-url
+    External,
+}
+
+/// Connection URLs of backends that are already running
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ExternalServices {
+    /// Connection URL of the CKAN database, used for sqlalchemy.url
+    pub postgres_url: String,
+    /// Defaults to the server of `postgres_url` with the datastore_default database
+    pub datastore_write_url: Option<String>,
+    /// Required when installing the DataStore extension
+    pub datastore_read_url: Option<String>,
+    pub solr_url: String,
+    pub redis_url: String,
+}
+
+/// Host ports the bundled services are published on
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServicePorts {
+    pub postgres: u16,
+    pub solr: u16,
+    pub redis: u16,
+}
+
+impl Default for ServicePorts {
+    fn default() -> Self {
+        ServicePorts {
+            postgres: 5432,
+            solr: 8983,
+            redis: 6379,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Services {
+    Compose {
+        #[serde(default)]
+        runtime: ContainerRuntime,
+        #[serde(default)]
+        ports: ServicePorts,
+    },
+    External(ExternalServices),
+}
+
+impl Default for Services {
+    fn default() -> Self {
+        Services::Compose {
+            runtime: ContainerRuntime::default(),
+            ports: ServicePorts::default(),
+        }
+    }
+}
+
+impl Services {
+    pub fn is_external(&self) -> bool {
+        matches!(self, Services::External(_))
+    }
+
+    /// Host ports of the bundled services, `None` for external services.
+    pub fn ports(&self) -> Option<ServicePorts> {
+        match self {
+            Services::Compose { ports, .. } => Some(*ports),
+            Services::External(_) => None,
+        }
+    }
+
+    pub fn postgres_url(&self) -> Result<String> {
+        match self {
+            Services::Compose { ports, .. } => Ok(format!(
+                "postgresql://ckan_default:{}@localhost:{}/ckan_default",
+                secrets::postgres_password()?,
+                ports.postgres
+            )),
+            Services::External(external) => Ok(external.postgres_url.clone()),
+        }
+    }
+
+    pub fn datastore_write_url(&self) -> Result<String> {
+        match self {
+            Services::Compose { ports, .. } => Ok(format!(
+                "postgresql://ckan_default:{}@localhost:{}/datastore_default",
+                secrets::postgres_password()?,
+                ports.postgres
+            )),
+            Services::External(external) => Ok(match &external.datastore_write_url {
+                Some(datastore_write_url) => datastore_write_url.clone(),
+                None => with_database(&external.postgres_url, "datastore_default"),
+            }),
+        }
+    }
+
+    pub fn datastore_read_url(&self) -> Result<String> {
+        match self {
+            Services::Compose { ports, .. } => Ok(format!(
+                "postgresql://datastore_default:{}@localhost:{}/datastore_default",
+                secrets::datastore_password()?,
+                ports.postgres
+            )),
+            Services::External(external) => external.datastore_read_url.clone().ok_or_else(|| {
+                anyhow!("Pass --datastore-read-url to install the DataStore extension with external services")
+            }),
+        }
+    }
+
+    pub fn solr_url(&self) -> String {
+        match self {
+            Services::Compose { ports, .. } => {
+                format!("http://127.0.0.1:{}/solr/ckan", ports.solr)
+            }
+            Services::External(external) => external.solr_url.clone(),
+        }
+    }
+
+    pub fn redis_url(&self) -> String {
+        match self {
+            Services::Compose { ports, .. } => format!("redis://localhost:{}/0", ports.redis),
+            Services::External(external) => external.redis_url.clone(),
+        }
+    }
+
+    /// Host, port, database number and password of the Redis server.
+    pub fn redis_address(&self) -> RedisAddress {
+        RedisAddress::parse(&self.redis_url())
+    }
+
+    /// Run a SQL file as the CKAN database user, inside the bundled PostgreSQL container or against
+    /// the external server.
+    pub fn run_sql_file(&self, sh: &Shell, sql_path: &Path) -> Result<()> {
+        match self {
+            Services::Compose { runtime, .. } => {
+                let postgres_container_id = crate::db::postgres_container_id(sh, *runtime)?;
+                runtime.cp(
+                    sh,
+                    &sql_path.to_string_lossy(),
+                    &format!("{postgres_container_id}:/ckan-devstaller.sql"),
+                )?;
+                runtime.exec(
+                    sh,
+                    &postgres_container_id,
+                    &[
+                        "psql",
+                        "-U",
+                        "ckan_default",
+                        "--set",
+                        "ON_ERROR_STOP=1",
+                        "-f",
+                        "/ckan-devstaller.sql",
+                    ],
+                )?;
+            }
+            Services::External(external) => {
+                let (postgres_url, password) =
+                    secrets::split_postgres_password(&external.postgres_url);
+                let mut psql = cmd!(
+                    sh,
+                    "psql {postgres_url} --set ON_ERROR_STOP=1 -f {sql_path}"
+                );
+                if let Some(password) = password {
+                    psql = psql.env("PGPASSWORD", password);
+                }
+                psql.run()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Connection settings of a Redis server, for extensions such as ckanext-harvest that do not
+/// read ckan.redis.url
+#[derive(Debug, PartialEq)]
+pub struct RedisAddress {
+    pub host: String,
+    pub port: String,
+    pub db: String,
+    pub password: Option<String>,
+}
+
+impl RedisAddress {
+    /// Parse a `redis://[[user]:password@]host[:port][/db]` URL, defaulting to port 6379 and
+    /// database 0.
+    fn parse(redis_url: &str) -> RedisAddress {
+        let address = redis_url
+            .split_once("://")
+            .map_or(redis_url, |(_, rest)| rest);
+        let address = address
+            .split_once('?')
+            .map_or(address, |(address, _)| address);
+        let (user_info, address) = match address.rsplit_once('@') {
+            Some((user_info, address)) => (Some(user_info), address),
+            None => (None, address),
+        };
+        let password = user_info
+            .map(|user_info| user_info.split_once(':').map_or(user_info, |(_, pw)| pw))
+            .filter(|password| !password.is_empty())
+            .map(secrets::percent_decode);
+        let (host_port, db) = address.split_once('/').unwrap_or((address, ""));
+        // The port follows the last colon, unless it is part of a bracketed IPv6 address
+        let (host, port) = match host_port.rsplit_once(':') {
+            Some((host, port)) if !port.ends_with(']') => (host, port),
+            _ => (host_port, ""),
+        };
+        let or_default =
+            |value: &str, default: &str| if value.is_empty() { default } else { value }.to_string();
+        RedisAddress {
+            host: or_default(host, "localhost"),
+            port: or_default(port, "6379"),
+            db: or_default(db, "0"),
+            password,
+        }
+    }
+}
+
+/// ckan-solr image with the CKAN Solr schema for a CKAN version.
+fn solr_image(ckan_version: &str) -> Result<String> {
+    versions::find(ckan_version)
+        .map(|version| version.solr_image.clone())
+        .ok_or_else(|| anyhow!("CKAN {ckan_version} is missing from the version catalog"))
+}
+
+/// Write the bundled compose file and start PostgreSQL (with the DataStore database and roles),
+/// Solr and Redis with the compose command of the container runtime, published on `ports` of
+/// 127.0.0.1. The database passwords come from the secrets file when installing with
+/// --secure, and PostgreSQL runs the PostGIS image when ckanext-spatial is enabled. A lockfile pins
+/// the images to the digests it records.
+pub fn step_start_compose_services(
+    step_prefix: String,
+    sh: &Shell,
+    username: &str,
+    config: &Config,
+    runtime: ContainerRuntime,
+    ports: ServicePorts,
+    lockfile: Option<&Lockfile>,
+) -> Result<()> {
+    println!(
+        "\n{} Starting PostgreSQL, Solr and Redis with {}...",
+        step_text(step_prefix.as_str()),
+        runtime.name()
+    );
+    let instance = instance::current();
+    let compose_project = instance.compose_project();
+    let compose_dir = instance.compose_dir(username);
+    std::fs::create_dir_all(&compose_dir)?;
+    let compose_dir = Path::new(&compose_dir);
+    std::fs::write(compose_dir.join("docker-compose.yml"), COMPOSE_FILE)?;
+    let postgres_init_path = compose_dir.join("postgres-init.sh");
+    std::fs::write(&postgres_init_path, POSTGRES_INIT_SCRIPT)?;
+    std::fs::set_permissions(&postgres_init_path, std::fs::Permissions::from_mode(0o755))?;
+    let postgres_image = if config.extension_ckanext_spatial {
+        POSTGIS_IMAGE
+    } else {
+        POSTGRES_IMAGE
+    };
+    let solr_image = solr_image(&config.ckan_version)?;
+    let image = |var: &str, image: &str| {
+        lockfile
+            .and_then(|lockfile| lockfile.images.get(var))
+            .map_or(image.to_string(), String::clone)
+    };
+    let postgres_image = image("POSTGRES_IMAGE", postgres_image);
+    let solr_image = image("CKAN_SOLR_IMAGE", &solr_image);
+    let redis_image = image("REDIS_IMAGE", REDIS_IMAGE);
+    let postgres_password = secrets::postgres_password()?;
+    let datastore_password = secrets::datastore_password()?;
+    let env_path = compose_dir.join(".env");
+    std::fs::write(
+        &env_path,
+        format!(
+            "COMPOSE_PROJECT_NAME={compose_project}\nPOSTGRES_IMAGE={postgres_image}\nCKAN_SOLR_IMAGE={solr_image}\nREDIS_IMAGE={redis_image}\nPOSTGRES_PORT={}\nSOLR_PORT={}\nREDIS_PORT={}\nPOSTGRES_PASSWORD={postgres_password}\nDATASTORE_READONLY_PASSWORD={datastore_password}\n",
+            ports.postgres, ports.solr, ports.redis
+        ),
+    )?;
+    std::fs::set_permissions(&env_path, std::fs::Permissions::from_mode(0o600))?;
+    compose_up(sh, username, runtime)?;
+    println!(
+        "{}",
+        success_text(format!("{step_prefix} Started PostgreSQL, Solr and Redis.").as_str())
+    );
+    Ok(())
+}
+
+/// Start the bundled services of the current instance, recreating the containers whose image or
+/// settings changed in the .env file, and wait until PostgreSQL accepts connections.
+pub fn compose_up(sh: &Shell, username: &str, runtime: ContainerRuntime) -> Result<()> {
+    let instance = instance::current();
+    let compose_project = instance.compose_project();
+    sh.change_dir(instance.compose_dir(username));
+    match runtime {
+        ContainerRuntime::Docker => runtime
+            .compose_cmd(sh)
+            .args(["-p", &compose_project, "up", "-d", "--wait"])
+            .run()?,
+        // podman-compose does not support --wait, so wait for PostgreSQL to accept connections
+        ContainerRuntime::Podman | ContainerRuntime::PodmanRootless => {
+            runtime
+                .compose_cmd(sh)
+                .args(["-p", &compose_project, "up", "-d"])
+                .run()?;
+            let postgres_container_id = crate::db::postgres_container_id(sh, runtime)?;
+            let started = std::time::Instant::now();
+            while runtime
+                .exec_read(
+                    sh,
+                    &postgres_container_id,
+                    &["pg_isready", "-U", "ckan_default"],
+                )
+                .is_err()
+            {
+                if started.elapsed() > std::time::Duration::from_secs(120) {
+                    return Err(anyhow!("PostgreSQL did not start within 120 seconds"));
+                }
+                std::thread::sleep(std::time::Duration::from_secs(2));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Images the bundled services of the current instance run, pinned by digest and keyed by the
+/// .env variable selecting them. Images without a registry digest are left out.
+pub fn service_images(sh: &Shell, runtime: ContainerRuntime) -> Result<BTreeMap<String, String>> {
+    let mut images = BTreeMap::new();
+    for (service, var) in SERVICE_IMAGE_VARS {
+        let container_id =
+            runtime.container_id(sh, &instance::current().container_name(service))?;
+        let image_id = runtime
+            .cmd(sh)
+            .args(["inspect", "--format", "{{.Image}}", &container_id])
+            .quiet()
+            .read()?;
+        let repo_digests = runtime
+            .cmd(sh)
+            .args([
+                "image",
+                "inspect",
+                "--format",
+                "{{range .RepoDigests}}{{.}} {{end}}",
+                &image_id,
+            ])
+            .quiet()
+            .read()?;
+        if let Some(digest) = repo_digests.split_whitespace().next() {
+            images.insert(var.to_string(), digest.to_string());
+        }
+    }
+    Ok(images)
+}
+
+/// Value of a variable of the current instance's compose .env file.
+pub fn compose_env(username: &str, key: &str) -> Result<Option<String>> {
+    let env_path = Path::new(&instance::current().compose_dir(username)).join(".env");
+    Ok(std::fs::read_to_string(env_path)?.lines().find_map(|line| {
+        line.split_once('=')
+            .filter(|(name, _)| *name == key)
+            .map(|(_, value)| value.to_string())
+    }))
+}
+
+/// Set a variable of the current instance's compose .env file, e.g. the image of a service.
+/// Returns whether the value changed, the services have to be restarted with [`compose_up`] to
+/// use it.
+pub fn set_compose_env(username: &str, key: &str, value: &str) -> Result<bool> {
+    let env_path = Path::new(&instance::current().compose_dir(username)).join(".env");
+    let env = std::fs::read_to_string(&env_path)?;
+    let (env, changed) = with_env_var(&env, key, value);
+    if changed {
+        std::fs::write(&env_path, env)?;
+    }
+    Ok(changed)
+}
+
+/// Replace or append `key=value` in the contents of a .env file.
+fn with_env_var(env: &str, key: &str, value: &str) -> (String, bool) {
+    let line = format!("{key}={value}");
+    let mut found = false;
+    let mut changed = false;
+    let mut lines = env
+        .lines()
+        .map(|current| {
+            if current.split_once('=').is_some_and(|(name, _)| name == key) {
+                found = true;
+                changed = current != line;
+                line.clone()
+            } else {
+                current.to_string()
+            }
+        })
+        .collect::<Vec<_>>();
+    if !found {
+        lines.push(line);
+        changed = true;
+    }
+    (format!("{}\n", lines.join("\n")), changed)
+}
+
+/// Switch the bundled Solr to the image of another CKAN series, e.g. after an upgrade, and
+/// rebuild the search index with its schema. Does nothing if the image is unchanged or the
+/// instance has no bundled services.
+pub fn step_update_solr_image(
+    step_prefix: String,
+    sh: &Shell,
+    username: &str,
+    solr_image: &str,
+    runtime: ContainerRuntime,
+) -> Result<()> {
+    let env_path = Path::new(&instance::current().compose_dir(username)).join(".env");
+    if !env_path.exists() {
+        return Ok(());
+    }
+    println!(
+        "\n{} Switching Solr to {solr_image}...",
+        step_text(step_prefix.as_str()),
+    );
+    if !set_compose_env(username, "CKAN_SOLR_IMAGE", solr_image)? {
+        println!(
+            "{}",
+            success_text(format!("{step_prefix} Solr already runs {solr_image}.").as_str())
+        );
+        return Ok(());
+    }
+    compose_up(sh, username, runtime)?;
+    let ckan_ini = instance::current().ini_path();
+    cmd!(sh, "ckan -c {ckan_ini} search-index rebuild").run()?;
+    println!(
+        "{}",
+        success_text(
+            format!("{step_prefix} Switched Solr to {solr_image} and rebuilt the search index.")
+                .as_str()
+        )
+    );
+    Ok(())
+}
+
+/// Point ckan.ini at the PostgreSQL, Solr and Redis servers and set the site URL and the file
+/// storage of the instance.
+pub fn step_configure_services(
+    step_prefix: String,
+    sh: &Shell,
+    services: &Services,
+    site_url: &str,
+) -> Result<()> {
+    let ckan_ini = instance::current().ini_path();
+    println!(
+        "\n{} Configuring the PostgreSQL, Solr and Redis servers and the site URL in {ckan_ini}...",
+        step_text(step_prefix.as_str()),
+    );
+    // The DataStore permissions are applied with psql on external servers
+    if services.is_external() {
+        cmd!(sh, "sudo apt install postgresql-client -y").run()?;
+    }
+    let mut conf = ini::Ini::load_from_file(&ckan_ini)?;
+    let app_main_section = conf
+        .section_mut(Some("app:main"))
+        .ok_or_else(|| anyhow!("Missing [app:main] section in {ckan_ini}"))?;
+    app_main_section.insert("sqlalchemy.url", services.postgres_url()?);
+    app_main_section.insert("solr_url", services.solr_url());
+    app_main_section.insert("ckan.redis.url", services.redis_url());
+    app_main_section.insert("ckan.site_url", site_url);
+    app_main_section.insert("ckan.storage_path", instance::current().storage_dir());
+    conf.write_to_file(&ckan_ini)?;
+    println!(
+        "{}",
+        success_text(
+            format!(
+                "{step_prefix} Configured the PostgreSQL, Solr and Redis servers and the site URL."
+            )
+            .as_str()
+        )
+    );
+    Ok(())
+}
+
+/// Replace the database of a PostgreSQL connection URL, keeping its credentials, server and
+/// query parameters such as sslmode.
+fn with_database(postgres_url: &str, database: &str) -> String {
+    let (scheme, rest) = postgres_url
+        .split_once("://")
+        .unwrap_or(("postgresql", postgres_url));
+    let (address, query) = match rest.split_once('?') {
+        Some((address, query)) => (address, Some(query)),
+        None => (rest, None),
+    };
+    // The path starts at the first slash after the credentials
+    let host_start = address.rfind('@').map_or(0, |at| at + 1);
+    let server = match address[host_start..].find('/') {
+        Some(slash) => &address[..host_start + slash],
+        None => address,
+    };
+    match query {
+        Some(query) => format!("{scheme}://{server}/{database}?{query}"),
+        None => format!("{scheme}://{server}/{database}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn with_env_var_replaces_an_existing_variable() {
+        let env = "COMPOSE_PROJECT_NAME=ckan\nPOSTGRES_IMAGE=postgres\nSOLR_PORT=8983\n";
+        assert_eq!(
+            with_env_var(env, "POSTGRES_IMAGE", "postgis"),
+            (
+                "COMPOSE_PROJECT_NAME=ckan\nPOSTGRES_IMAGE=postgis\nSOLR_PORT=8983\n".to_string(),
+                true
+            )
+        );
+    }
+
+    #[test]
+    fn with_env_var_appends_a_missing_variable() {
+        let (env, changed) = with_env_var("POSTGRES_PORT=5432\n", "POSTGRES_IMAGE", "postgis");
+        assert_eq!(env, "POSTGRES_PORT=5432\nPOSTGRES_IMAGE=postgis\n");
+        assert!(changed);
+    }
+
+    #[test]
+    fn with_env_var_reports_an_unchanged_value() {
+        let (_, changed) = with_env_var("POSTGRES_IMAGE=postgis\n", "POSTGRES_IMAGE", "postgis");
+        assert!(!changed);
+    }
+
+    #[test]
+    fn with_env_var_does_not_match_a_prefix() {
+        let (env, _) = with_env_var("POSTGRES_IMAGE_TAG=16\n", "POSTGRES_IMAGE", "postgis");
+        assert_eq!(env, "POSTGRES_IMAGE_TAG=16\nPOSTGRES_IMAGE=postgis\n");
+    }
+
+    #[test]
+    fn with_database_replaces_the_database() {
+        assert_eq!(
+            with_database(
+                "postgresql://u:p@db.example.com:5432/ckan",
+                "datastore_default"
+            ),
+            "postgresql://u:p@db.example.com:5432/datastore_default"
+        );
+    }
+
+    #[test]
+    fn with_database_adds_a_missing_database() {
+        assert_eq!(
+            with_database("postgresql://u:p@host", "datastore_default"),
+            "postgresql://u:p@host/datastore_default"
+        );
+        assert_eq!(
+            with_database("postgresql://u:p@host/", "datastore_default"),
+            "postgresql://u:p@host/datastore_default"
+        );
+    }
+
+    #[test]
+    fn with_database_keeps_the_query() {
+        assert_eq!(
+            with_database(
+                "postgresql://u:p@host/ckan?sslmode=require",
+                "datastore_default"
+            ),
+            "postgresql://u:p@host/datastore_default?sslmode=require"
+        );
+        assert_eq!(
+            with_database("postgresql://u:p@host?sslmode=require", "datastore_default"),
+            "postgresql://u:p@host/datastore_default?sslmode=require"
+        );
+    }
+
+    #[test]
+    fn with_database_ignores_slashes_in_the_password() {
+        assert_eq!(
+            with_database("postgresql://u:a/b@host/ckan", "datastore_default"),
+            "postgresql://u:a/b@host/datastore_default"
+        );
+    }
+
+    #[test]
+    fn with_database_keeps_a_socket_url() {
+        assert_eq!(
+            with_database(
+                "postgresql:///ckan?host=/var/run/postgresql",
+                "datastore_default"
+            ),
+            "postgresql:///datastore_default?host=/var/run/postgresql"
+        );
+    }
+
+    #[test]
+    fn redis_address_defaults_the_port_and_database() {
+        assert_eq!(
+            RedisAddress::parse("redis://redis.example.com"),
+            RedisAddress {
+                host: "redis.example.com".to_string(),
+                port: "6379".to_string(),
+                db: "0".to_string(),
+                password: None,
+            }
+        );
+    }
+
+    #[test]
+    fn redis_address_keeps_the_password() {
+        assert_eq!(
+            RedisAddress::parse("redis://:s%40cret@host:6380/2"),
+            RedisAddress {
+                host: "host".to_string(),
+                port: "6380".to_string(),
+                db: "2".to_string(),
+                password: Some("s@cret".to_string()),
+            }
+        );
+        assert_eq!(
+            RedisAddress::parse("redis://user:pw@host/1").password,
+            Some("pw".to_string())
+        );
+    }
+
+    #[test]
+    fn redis_address_reads_ipv6_hosts() {
+        let address = RedisAddress::parse("redis://[::1]:6380/1?protocol=3");
+        assert_eq!(
+            (address.host.as_str(), address.port.as_str()),
+            ("[::1]", "6380")
+        );
+        assert_eq!(address.db, "1");
+        assert_eq!(RedisAddress::parse("redis://[::1]").port, "6379");
+    }
+}
@@ -0,0 +1,135 @@
+use crate::{
+    config::Config,
+    db, instance,
+    lockfile::{self, Lockfile},
+    secrets::Secrets,
+    styles::{important_text, step_text, success_text},
+    validation,
+};
+use anyhow::{Result, anyhow};
+use std::{
+    fs::{OpenOptions, Permissions},
+    os::unix::fs::{OpenOptionsExt, PermissionsExt},
+    path::{Path, PathBuf},
+};
+use xshell::{Shell, cmd};
+use xshell_venv::VirtualEnv;
+
+const EXPORT_DIR: &str = "/tmp/ckan-devstaller-snapshot-export";
+const IMPORT_DIR: &str = "/tmp/ckan-devstaller-snapshot-import";
+
+/// Bundle the effective config, ckan.ini, lockfile, database dumps and file storage into a single
+/// archive, returning its path.
+pub fn export(sh: &Shell, username: &str, output: Option<PathBuf>) -> Result<PathBuf> {
+    let ckan_ini = instance::current().ini_path();
+    let config_path = instance::current().config_path();
+    if !std::fs::exists(&config_path)? {
+        return Err(anyhow!(
+            "Could not find {config_path}, snapshots require an installation made by this version of ckan-devstaller"
+        ));
+    }
+    let output = match output {
+        Some(output) => output,
+        None => {
+            let timestamp = cmd!(sh, "date +%Y%m%d-%H%M%S").read()?;
+            PathBuf::from(format!(
+                "/home/{username}/ckan-devstaller-snapshot-{timestamp}.tar.gz"
+            ))
+        }
+    };
+    cmd!(sh, "sudo rm -rf {EXPORT_DIR}").run()?;
+    std::fs::create_dir_all(EXPORT_DIR)?;
+    let export_dir = Path::new(EXPORT_DIR);
+
+    println!(
+        "\n{} Saving the config, ckan.ini and lockfile...",
+        step_text("1.")
+    );
+    std::fs::copy(&config_path, export_dir.join("ckan-devstaller.json"))?;
+    std::fs::copy(&ckan_ini, export_dir.join("ckan.ini"))?;
+    if let Some(secrets) = Secrets::load()? {
+        secrets.save(&export_dir.join("ckan-devstaller.secrets.json"))?;
+    }
+    lockfile::read_installed(sh)?.save(&export_dir.join("ckan-devstaller.lock.json"))?;
+    println!(
+        "{}",
+        success_text("1. Saved the config, ckan.ini and lockfile.")
+    );
+
+    let backup = db::backup(sh, username, Some("snapshot".to_string()))?;
+    // Copied rather than renamed, as the backups directory may be on another filesystem than /tmp
+    std::fs::copy(&backup, export_dir.join("data.tar.gz"))?;
+    std::fs::remove_file(&backup)?;
+
+    // The archive contains ckan.ini and the secrets, so it is only readable by the current user
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&output)?;
+    std::fs::set_permissions(&output, Permissions::from_mode(0o600))?;
+    cmd!(sh, "tar -czf {output} -C {EXPORT_DIR} .").run()?;
+    cmd!(sh, "sudo rm -rf {EXPORT_DIR}").run()?;
+    println!(
+        "\n{}",
+        success_text(format!("Exported the snapshot to {}.", output.display()).as_str())
+    );
+    Ok(output)
+}
+
+/// Recreate the environment of a snapshot with the normal install steps and restore its data.
+pub fn import(sh: &Shell, username: &str, archive: &Path) -> Result<()> {
+    let ckan_ini = instance::current().ini_path();
+    let venv_dir = instance::current().venv_dir();
+    cmd!(sh, "sudo rm -rf {IMPORT_DIR}").run()?;
+    std::fs::create_dir_all(IMPORT_DIR)?;
+    cmd!(sh, "tar -xzf {archive} -C {IMPORT_DIR}").run()?;
+    let import_dir = Path::new(IMPORT_DIR);
+    let mut config: Config = serde_json::from_str(&std::fs::read_to_string(
+        import_dir.join("ckan-devstaller.json"),
+    )?)?;
+    // The snapshot's data replaces anything seeded during the installation
+    config.seed_data = false;
+    validation::validate_config(&config)?;
+    instance::check_ports(instance::current(), &config)?;
+
+    println!(
+        "{}",
+        important_text(
+            format!(
+                "Importing the snapshot {} with CKAN {}...",
+                archive.display(),
+                config.ckan_version
+            )
+            .as_str()
+        )
+    );
+    let lockfile = Lockfile::load(&import_dir.join("ckan-devstaller.lock.json"))?;
+    // The restored ckan.ini and databases use the snapshot's passwords
+    let secrets_path = import_dir.join("ckan-devstaller.secrets.json");
+    if secrets_path.exists() {
+        let config_dir = instance::current().config_dir();
+        cmd!(sh, "sudo mkdir -p {config_dir}").run()?;
+        cmd!(sh, "sudo chown -R {username} /etc/ckan/").run()?;
+        std::fs::copy(&secrets_path, instance::current().secrets_path())?;
+    }
+    crate::install(sh, &config, username, true, Some(&lockfile))?;
+
+    let venv_path = PathBuf::from(&venv_dir);
+    let _venv = VirtualEnv::with_path(sh, &venv_path)?;
+
+    std::fs::copy(import_dir.join("ckan.ini"), &ckan_ini)?;
+    db::restore(
+        sh,
+        username,
+        import_dir.join("data.tar.gz").to_string_lossy().as_ref(),
+        true,
+    )?;
+    cmd!(sh, "sudo rm -rf {IMPORT_DIR}").run()?;
+    println!(
+        "\n{}",
+        success_text(format!("Imported the snapshot {}.", archive.display()).as_str())
+    );
+    Ok(())
+}
@@ -0,0 +1,179 @@
+use crate::{
+    config::Config,
+    container::ContainerRuntime,
+    db,
+    extensions::{EXTENSIONS, installed_plugins},
+    instance, python,
+    services::{Services, step_update_solr_image},
+    steps::add_datapusher_plus_resource_formats,
+    styles::{highlighted_text, important_text, step_text, success_text},
+    validation::validate_ckan_version,
+    versions::{self, CkanVersion, parse_version},
+};
+use anyhow::{Result, anyhow};
+use std::path::Path;
+use xshell::{Shell, cmd};
+
+/// Plugins shipped with CKAN itself, which are upgraded along with CKAN
+const CORE_PLUGINS: &[&str] = &[
+    "activity",
+    "audio_view",
+    "datapusher",
+    "datastore",
+    "datatables_view",
+    "envvars",
+    "expire_api_token",
+    "image_view",
+    "recline_view",
+    "resource_proxy",
+    "stats",
+    "text_view",
+    "tracking",
+    "video_view",
+    "webpage_view",
+];
+
+pub fn upgrade(sh: &Shell, username: &str, ckan_version: &str, force: bool) -> Result<()> {
+    validate_ckan_version(ckan_version)?;
+    let target = versions::find(ckan_version)
+        .ok_or_else(|| anyhow!("CKAN {ckan_version} is missing from the version catalog"))?;
+    let ckan_ini = instance::current().ini_path();
+    let src_dir = instance::current().src_dir();
+    let saved_config = Config::load_saved()?;
+    if let Some(config) = &saved_config
+        && !config.ckan_source.is_release()
+    {
+        return Err(anyhow!(
+            "CKAN was installed from {}, update its checkout in {src_dir}/ckan with git instead",
+            config.ckan_source
+        ));
+    }
+    let venv_dir = instance::current().venv_dir();
+    let installed_version = cmd!(sh, "python -c 'import ckan; print(ckan.__version__)'").read()?;
+    println!(
+        "Upgrading CKAN {} to CKAN {}...",
+        highlighted_text(installed_version.as_str()),
+        highlighted_text(ckan_version)
+    );
+    if parse_version(ckan_version) <= parse_version(&installed_version) {
+        return Err(anyhow!(
+            "CKAN {ckan_version} is not newer than the installed CKAN {installed_version}"
+        ));
+    }
+    let plugins = installed_plugins()?;
+    check_compatibility(&plugins, target, force)?;
+
+    db::backup(
+        sh,
+        username,
+        Some(format!("pre-upgrade-{installed_version}")),
+    )?;
+
+    println!(
+        "\n{} Checking out CKAN {} in {src_dir}/ckan...",
+        step_text("4."),
+        ckan_version
+    );
+    sh.change_dir(format!("{src_dir}/ckan"));
+    cmd!(sh, "git fetch --tags origin").run()?;
+    // DataPusher+ adds the TAB format to the resource formats of the checkout, which would make
+    // the checkout fail on local changes, so it is reverted here and added again afterwards
+    let datapusher_plus = plugins.iter().any(|plugin| plugin == "datapusher_plus");
+    if datapusher_plus {
+        cmd!(sh, "git checkout -- ckan/config/resource_formats.json").run()?;
+    }
+    cmd!(sh, "git checkout ckan-{ckan_version}").run()?;
+    if datapusher_plus {
+        add_datapusher_plus_resource_formats(&src_dir)?;
+    }
+    println!(
+        "{}",
+        success_text(format!("4. Checked out CKAN {ckan_version}.").as_str())
+    );
+
+    println!(
+        "\n{} Reinstalling CKAN and its requirements into {venv_dir}...",
+        step_text("5."),
+    );
+    python::pip_install(sh, &["-r", "requirements.txt"])?;
+    python::pip_install(sh, &["-e", "."])?;
+    println!(
+        "{}",
+        success_text("5. Reinstalled CKAN and its requirements.")
+    );
+
+    println!("\n{} Running database migrations...", step_text("6."));
+    cmd!(sh, "ckan -c {ckan_ini} db upgrade").run()?;
+    // Only extensions that ship migrations have any, core plugins are migrated by `db upgrade`
+    for plugin in EXTENSIONS
+        .iter()
+        .flat_map(|extension| extension.migration_plugins)
+        .filter(|plugin| plugins.iter().any(|p| p == *plugin))
+    {
+        cmd!(sh, "ckan -c {ckan_ini} db upgrade -p {plugin}").run()?;
+    }
+    println!("{}", success_text("6. Ran database migrations."));
+
+    // The bundled Solr has to serve the schema of the target series
+    let runtime = match saved_config.as_ref().map(|config| &config.services) {
+        Some(Services::Compose { runtime, .. }) => Some(*runtime),
+        Some(Services::External(_)) => None,
+        None => Some(ContainerRuntime::detect(sh)),
+    };
+    if let Some(runtime) = runtime {
+        step_update_solr_image("7.".to_string(), sh, username, &target.solr_image, runtime)?;
+    }
+
+    if let Some(config) = saved_config {
+        Config {
+            ckan_version: ckan_version.to_string(),
+            ..config
+        }
+        .save(Path::new(&instance::current().config_path()))?;
+    }
+    println!(
+        "\n{}",
+        success_text(format!("Upgraded to CKAN {ckan_version}.").as_str())
+    );
+    Ok(())
+}
+
+/// Refuse to upgrade when an installed extension has no ref known to work with the target CKAN
+/// version in the version catalog, and warn about extensions that are not in the registry.
+fn check_compatibility(plugins: &[String], target: &CkanVersion, force: bool) -> Result<()> {
+    for plugin in plugins {
+        if CORE_PLUGINS.contains(&plugin.as_str()) {
+            continue;
+        }
+        let extension = EXTENSIONS
+            .iter()
+            .find(|extension| extension.plugins.contains(&plugin.as_str()));
+        match extension {
+            // Extensions shipped with CKAN are upgraded along with it
+            Some(extension) if extension.src_dir.is_none() => {}
+            Some(extension) if target.extension_ref(extension.name).is_none() => {
+                let message = format!(
+                    "The {plugin} plugin of {} is not known to support CKAN {}.",
+                    extension.name,
+                    target.series()
+                );
+                if !force {
+                    return Err(anyhow!("{message} Use --force to upgrade anyway."));
+                }
+                println!("{}", important_text(message.as_str()));
+            }
+            Some(_) => {}
+            None => println!(
+                "{}",
+                important_text(
+                    format!(
+                        "The compatibility of the {plugin} plugin with CKAN {} is unknown.",
+                        target.series()
+                    )
+                    .as_str()
+                )
+            ),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,243 @@
+use crate::{config::Config, python::Installer, versions};
+use anyhow::{Result, anyhow};
+use inquire::validator::{StringValidator, Validation};
+
+/// CKAN's rules for user names: 2 to 100 lowercase alphanumeric characters, - and _.
+pub fn validate_username(username: &str) -> Result<()> {
+    if !(2..=100).contains(&username.len()) {
+        return Err(anyhow!(
+            "The username must be between 2 and 100 characters long"
+        ));
+    }
+    if !username
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+    {
+        return Err(anyhow!(
+            "The username must only contain lowercase alphanumeric characters, - and _"
+        ));
+    }
+    Ok(())
+}
+
+/// CKAN's rule for passwords: at least 8 characters.
+pub fn validate_password(password: &str) -> Result<()> {
+    if password.chars().count() < 8 {
+        return Err(anyhow!("The password must be at least 8 characters long"));
+    }
+    Ok(())
+}
+
+/// CKAN's rules for emails: a local part and a domain of dot separated labels of alphanumeric
+/// characters and inner -.
+pub fn validate_email(email: &str) -> Result<()> {
+    let invalid = || anyhow!("{email} is not a valid email address");
+    let (local_part, domain) = email.split_once('@').ok_or_else(invalid)?;
+    if local_part.is_empty()
+        || !local_part
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || ".!#$%&'*+/=?^_`{|}~-".contains(c))
+    {
+        return Err(invalid());
+    }
+    let valid_label = |label: &str| {
+        (1..=63).contains(&label.len())
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+    };
+    if !domain.split('.').all(valid_label) {
+        return Err(invalid());
+    }
+    Ok(())
+}
+
+/// A CKAN release such as 2.11.5 from one of the series in the version catalog, no newer than the
+/// release the catalog lists for its series.
+pub fn validate_ckan_version(ckan_version: &str) -> Result<()> {
+    let parts = ckan_version.split('.').collect::<Vec<_>>();
+    if parts.len() != 3
+        || parts
+            .iter()
+            .any(|part| part.is_empty() || !part.chars().all(|c| c.is_ascii_digit()))
+    {
+        return Err(anyhow!(
+            "{ckan_version} is not a CKAN release version such as 2.11.5"
+        ));
+    }
+    let Some(version) = versions::find(ckan_version) else {
+        return Err(anyhow!(
+            "CKAN {ckan_version} is not supported, choose a version from CKAN {} (see `ckan-devstaller versions list`)",
+            versions::supported_series().join(", ")
+        ));
+    };
+    // Earlier patch releases of a series are installable, releases newer than the catalog are unknown
+    if versions::parse_version(ckan_version) > versions::parse_version(&version.version) {
+        return Err(anyhow!(
+            "CKAN {ckan_version} is not a known release, the newest release of CKAN {} is {}",
+            version.series(),
+            version.version
+        ));
+    }
+    Ok(())
+}
+
+pub fn validate_site_url(site_url: &str) -> Result<()> {
+    if !site_url.starts_with("http://") && !site_url.starts_with("https://") {
+        return Err(anyhow!(
+            "The site URL {site_url} must start with http:// or https://"
+        ));
+    }
+    Ok(())
+}
+
+/// Apply the rules of the interactive questions to a config given with CLI options or a config
+/// file.
+pub fn validate_config(config: &Config) -> Result<()> {
+    validate_ckan_version(&config.ckan_version)?;
+    validate_username(&config.sysadmin.username)?;
+    validate_email(&config.sysadmin.email)?;
+    // With --secure the sysadmin password is generated
+    if !config.secure {
+        validate_password(&config.sysadmin.password)?;
+    }
+    if let Some(site_url) = &config.site_url {
+        validate_site_url(site_url)?;
+    }
+    validate_python(config)
+}
+
+/// The Python version must be supported by the CKAN version, and can only be chosen with uv.
+pub fn validate_python(config: &Config) -> Result<()> {
+    if config.python.is_some() && config.installer == Installer::Pip {
+        return Err(anyhow!(
+            "--python requires --installer uv, pip uses the system python3"
+        ));
+    }
+    let Some(version) = versions::find(&config.ckan_version) else {
+        return Ok(());
+    };
+    match config.python_version() {
+        Some(python) if !version.supports_python(&python) => Err(anyhow!(
+            "CKAN {} supports Python {} to {}, not Python {python}",
+            config.ckan_version,
+            version.python.min,
+            version.python.max
+        )),
+        None if config.installer == Installer::Uv => Err(anyhow!(
+            "CKAN {} does not support any of the Python versions uv can install",
+            config.ckan_version
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Use one of the validation functions as an inquire validator.
+pub fn validator(validate: fn(&str) -> Result<()>) -> impl StringValidator {
+    move |input: &str| {
+        Ok(match validate(input) {
+            Ok(()) => Validation::Valid,
+            Err(e) => Validation::Invalid(e.to_string().into()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn config(installer: &str, python: Option<&str>, ckan_version: &str) -> Config {
+        serde_json::from_value(json!({
+            "ssh": false,
+            "ckan_version": ckan_version,
+            "installer": installer,
+            "python": python,
+            "sysadmin": {
+                "username": "ckan_admin",
+                "password": "password",
+                "email": "ckan_admin@localhost"
+            },
+            "extension_datastore": true,
+            "extension_ckanext_scheming": true,
+            "extension_datapusher_plus": true,
+            "druf_mode": false,
+            "seed_data": false
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn validate_username_checks_length_and_characters() {
+        assert!(validate_username("ckan_admin-2").is_ok());
+        assert!(validate_username("a").is_err());
+        assert!(validate_username(&"a".repeat(101)).is_err());
+        assert!(validate_username("CkanAdmin").is_err());
+        assert!(validate_username("ckan admin").is_err());
+    }
+
+    #[test]
+    fn validate_password_counts_characters() {
+        assert!(validate_password("12345678").is_ok());
+        assert!(validate_password("1234567").is_err());
+        // 7 characters in 9 bytes
+        assert!(validate_password("pässwör").is_err());
+    }
+
+    #[test]
+    fn validate_email_checks_local_part_and_domain() {
+        assert!(validate_email("ckan_admin@localhost").is_ok());
+        assert!(validate_email("first.last+ckan@data.example.org").is_ok());
+        assert!(validate_email("ckan_admin").is_err());
+        assert!(validate_email("@localhost").is_err());
+        assert!(validate_email("ckan admin@localhost").is_err());
+        assert!(validate_email("ckan_admin@").is_err());
+        assert!(validate_email("ckan_admin@-example.org").is_err());
+        assert!(validate_email("ckan_admin@example..org").is_err());
+    }
+
+    #[test]
+    fn validate_ckan_version_requires_a_supported_release() {
+        assert!(validate_ckan_version("2.11.5").is_ok());
+        // An earlier patch release of a catalog series
+        assert!(validate_ckan_version("2.10.4").is_ok());
+        assert!(validate_ckan_version("2.11").is_err());
+        assert!(validate_ckan_version("2.11.x").is_err());
+        assert!(validate_ckan_version("2.8.12").is_err());
+    }
+
+    #[test]
+    fn validate_ckan_version_rejects_releases_newer_than_the_catalog() {
+        assert!(validate_ckan_version("2.11.99").is_err());
+        assert!(validate_ckan_version("2.10.11").is_err());
+    }
+
+    #[test]
+    fn validate_site_url_requires_http() {
+        assert!(validate_site_url("http://localhost:5000").is_ok());
+        assert!(validate_site_url("https://ckan.example.org").is_ok());
+        assert!(validate_site_url("ckan.example.org").is_err());
+    }
+
+    #[test]
+    fn validate_python_checks_the_ckan_version_range() {
+        assert!(validate_python(&config("uv", Some("3.12"), "2.11.5")).is_ok());
+        assert!(validate_python(&config("uv", None, "2.10.10")).is_ok());
+        assert!(validate_python(&config("uv", Some("3.12"), "2.10.10")).is_err());
+    }
+
+    #[test]
+    fn validate_config_skips_the_generated_password() {
+        let mut config = config("uv", None, "2.11.5");
+        config.sysadmin.password = String::new();
+        assert!(validate_config(&config).is_err());
+        config.secure = true;
+        assert!(validate_config(&config).is_ok());
+    }
+
+    #[test]
+    fn validate_python_requires_uv() {
+        assert!(validate_python(&config("pip", None, "2.11.5")).is_ok());
+        assert!(validate_python(&config("pip", Some("3.11"), "2.11.5")).is_err());
+    }
+}
@@ -0,0 +1,219 @@
+use crate::{
+    ckan_api::{self, ckan_url},
+    instance,
+    styles::{highlighted_text, important_text, step_text, success_text},
+};
+use anyhow::{Result, anyhow};
+use serde_json::json;
+use std::time::{Duration, Instant};
+use xshell::{Shell, cmd};
+
+const VERIFY_CSV: &str = include_str!("../fixtures/verify.csv");
+const VERIFY_CSV_PATH: &str = "/tmp/ckan-devstaller-verify.csv";
+const VERIFY_CSV_ROWS: u64 = 5;
+const DATAPUSHER_PLUS_TIMEOUT: Duration = Duration::from_secs(180);
+
+pub fn verify(sh: &Shell, deep: bool, sysadmin_username: String) -> Result<()> {
+    let ckan_url = ckan_url();
+    println!(
+        "\n{} Checking that CKAN is responding at {}...",
+        step_text("1."),
+        highlighted_text(&ckan_url)
+    );
+    let status = ckan_api::action(sh, None, "status_show", &json!({})).map_err(|e| {
+        anyhow!("CKAN is not responding at {ckan_url}, make sure it is running ({e})")
+    })?;
+    println!(
+        "{}",
+        success_text(
+            format!(
+                "1. CKAN {} is running with the plugins: {}",
+                status["ckan_version"]
+                    .as_str()
+                    .unwrap_or("(unknown version)"),
+                status["extensions"]
+            )
+            .as_str()
+        )
+    );
+    if !deep {
+        return Ok(());
+    }
+
+    let plugins = status["extensions"].as_array().cloned().unwrap_or_default();
+    for plugin in ["datastore", "datapusher_plus"] {
+        if !plugins.iter().any(|p| p.as_str() == Some(plugin)) {
+            return Err(anyhow!(
+                "The {plugin} plugin is not enabled, the deep verification requires both the DataStore and DataPusher+ extensions"
+            ));
+        }
+    }
+
+    println!(
+        "\n{} Checking the DataPusher+ prerequisites...",
+        step_text("2.")
+    );
+    check_datapusher_plus_prerequisites(sh)?;
+    println!(
+        "{}",
+        success_text("2. Found qsvdp, the en_US.UTF-8 locale and a DataPusher+ API token.")
+    );
+
+    println!(
+        "\n{} Creating a throwaway organization and dataset as {}...",
+        step_text("3."),
+        highlighted_text(sysadmin_username.as_str())
+    );
+    let api_token = ckan_api::create_api_token(sh, &sysadmin_username, "devstaller-verify")?;
+    let suffix = cmd!(sh, "date +%s").read()?;
+    let org_name = format!("devstaller-verify-{suffix}");
+    let dataset_name = format!("devstaller-verify-{suffix}");
+    let result = push_and_search(sh, &api_token, &org_name, &dataset_name);
+
+    println!("\n{} Cleaning up...", step_text("5."));
+    let cleanup_result = cleanup(sh, &api_token, &org_name, &dataset_name);
+    std::fs::remove_file(VERIFY_CSV_PATH).ok();
+    result?;
+    cleanup_result?;
+    println!(
+        "{}",
+        success_text("5. Removed the throwaway dataset, organization and API token.")
+    );
+    println!(
+        "\n{}",
+        success_text("DataStore and DataPusher+ are working as expected.")
+    );
+    Ok(())
+}
+
+fn check_datapusher_plus_prerequisites(sh: &Shell) -> Result<()> {
+    let ckan_ini = instance::current().ini_path();
+    let conf = ini::Ini::load_from_file(&ckan_ini)?;
+    let app_main_section = conf
+        .section(Some("app:main"))
+        .ok_or_else(|| anyhow!("Missing [app:main] section in {ckan_ini}"))?;
+    let qsv_bin = app_main_section
+        .get("ckanext.datapusher_plus.qsv_bin")
+        .unwrap_or("/usr/local/bin/qsvdp");
+    cmd!(sh, "{qsv_bin} --version")
+        .quiet()
+        .ignore_stdout()
+        .run()
+        .map_err(|_| {
+            anyhow!("Could not run qsvdp at {qsv_bin}, check ckanext.datapusher_plus.qsv_bin")
+        })?;
+    let api_token = app_main_section
+        .get("ckanext.datapusher_plus.api_token")
+        .unwrap_or_default();
+    if api_token.is_empty() || api_token.starts_with('<') {
+        return Err(anyhow!(
+            "ckanext.datapusher_plus.api_token is not set in {ckan_ini}"
+        ));
+    }
+    let locales = cmd!(sh, "locale -a").quiet().read()?;
+    if !locales
+        .lines()
+        .any(|locale| locale.eq_ignore_ascii_case("en_US.utf8"))
+    {
+        return Err(anyhow!(
+            "The en_US.UTF-8 locale is missing, run {} and {}",
+            highlighted_text("sudo locale-gen en_US.UTF-8"),
+            highlighted_text("sudo update-locale")
+        ));
+    }
+    Ok(())
+}
+
+fn push_and_search(sh: &Shell, api_token: &str, org_name: &str, dataset_name: &str) -> Result<()> {
+    let ckan_ini = instance::current().ini_path();
+    ckan_api::action(
+        sh,
+        Some(api_token),
+        "organization_create",
+        &json!({ "name": org_name, "title": "ckan-devstaller verification" }),
+    )?;
+    ckan_api::action(
+        sh,
+        Some(api_token),
+        "package_create",
+        &json!({ "name": dataset_name, "owner_org": org_name, "private": true }),
+    )?;
+    println!(
+        "{}",
+        success_text(format!("3. Created the {dataset_name} dataset.").as_str())
+    );
+
+    println!(
+        "\n{} Uploading a CSV file and waiting for DataPusher+ to load it into the DataStore...",
+        step_text("4.")
+    );
+    std::fs::write(VERIFY_CSV_PATH, VERIFY_CSV)?;
+    let resource = ckan_api::upload_resource(
+        sh,
+        api_token,
+        VERIFY_CSV_PATH,
+        &json!({ "package_id": dataset_name, "format": "CSV" }),
+    )?;
+    let resource_id = resource["id"]
+        .as_str()
+        .ok_or_else(|| anyhow!("resource_create did not return a resource id"))?;
+    let started = Instant::now();
+    loop {
+        std::thread::sleep(Duration::from_secs(5));
+        let search = ckan_api::action(
+            sh,
+            Some(api_token),
+            "datastore_search",
+            &json!({ "resource_id": resource_id, "limit": 1 }),
+        );
+        if let Ok(search) = search
+            && search["total"].as_u64() == Some(VERIFY_CSV_ROWS)
+        {
+            break;
+        }
+        if started.elapsed() > DATAPUSHER_PLUS_TIMEOUT {
+            println!(
+                "{}",
+                important_text(
+                    format!("Make sure a background jobs worker is running with: ckan -c {ckan_ini} jobs worker").as_str()
+                )
+            );
+            return Err(anyhow!(
+                "DataPusher+ did not load the resource {resource_id} into the DataStore within {} seconds",
+                DATAPUSHER_PLUS_TIMEOUT.as_secs()
+            ));
+        }
+    }
+    println!(
+        "{}",
+        success_text(
+            format!("4. datastore_search returned all {VERIFY_CSV_ROWS} rows of the uploaded CSV.")
+                .as_str()
+        )
+    );
+    Ok(())
+}
+
+fn cleanup(sh: &Shell, api_token: &str, org_name: &str, dataset_name: &str) -> Result<()> {
+    let dataset_result = ckan_api::action(
+        sh,
+        Some(api_token),
+        "dataset_purge",
+        &json!({ "id": dataset_name }),
+    );
+    let org_result = ckan_api::action(
+        sh,
+        Some(api_token),
+        "organization_purge",
+        &json!({ "id": org_name }),
+    );
+    ckan_api::action(
+        sh,
+        Some(api_token),
+        "api_token_revoke",
+        &json!({ "token": api_token }),
+    )?;
+    dataset_result?;
+    org_result?;
+    Ok(())
+}
@@ -0,0 +1,191 @@
+use crate::{python::PYTHON_VERSIONS, styles::highlighted_text};
+use serde::Deserialize;
+use std::{collections::BTreeMap, sync::OnceLock};
+
+/// Catalog of the supported CKAN releases, newest first. Also used by the docs builder.
+const CATALOG: &str = include_str!("../ckan-versions.json");
+
+static VERSIONS: OnceLock<Vec<CkanVersion>> = OnceLock::new();
+
+/// A CKAN release that ckan-devstaller knows how to install
+#[derive(Deserialize)]
+pub struct CkanVersion {
+    pub version: String,
+    /// Python versions the release supports
+    pub python: PythonRange,
+    /// ckan-solr image with the Solr schema of the release
+    pub solr_image: String,
+    /// Name of the Solr schema shipped with the release
+    pub solr_schema: String,
+    /// Branch, tag or commit of each supported extension known to work with the release
+    pub extension_refs: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+pub struct PythonRange {
+    pub min: String,
+    pub max: String,
+}
+
+impl CkanVersion {
+    /// Minor version of the release, e.g. 2.11
+    pub fn series(&self) -> String {
+        series(&self.version)
+    }
+
+    /// Whether a Python version such as 3.11 is within the range supported by the release.
+    pub fn supports_python(&self, python: &str) -> bool {
+        let python = parse_version(python);
+        parse_version(&self.python.min) <= python && python <= parse_version(&self.python.max)
+    }
+
+    /// Newest of the [`PYTHON_VERSIONS`] supported by the release, used by uv when --python is
+    /// not given.
+    pub fn default_python(&self) -> Option<&'static str> {
+        PYTHON_VERSIONS
+            .iter()
+            .rev()
+            .find(|python| self.supports_python(python))
+            .copied()
+    }
+
+    /// Ref of an extension known to work with the release, if any.
+    pub fn extension_ref(&self, name: &str) -> Option<&str> {
+        self.extension_refs.get(name).map(String::as_str)
+    }
+}
+
+/// Supported CKAN releases, newest first.
+pub fn catalog() -> &'static [CkanVersion] {
+    VERSIONS.get_or_init(|| {
+        serde_json::from_str(CATALOG).expect("ckan-versions.json should be a valid catalog")
+    })
+}
+
+/// CKAN version installed when --ckan-version is not given.
+pub fn default_version() -> &'static CkanVersion {
+    &catalog()[0]
+}
+
+/// Catalog entry of the series a CKAN version belongs to, so that e.g. 2.11.4 uses the Python
+/// range and Solr image of 2.11.5.
+pub fn find(ckan_version: &str) -> Option<&'static CkanVersion> {
+    let series = series(ckan_version);
+    catalog()
+        .iter()
+        .find(|version| version.version == ckan_version)
+        .or_else(|| catalog().iter().find(|version| version.series() == series))
+}
+
+/// Supported minor versions, e.g. 2.11 and 2.10.
+pub fn supported_series() -> Vec<String> {
+    catalog().iter().map(CkanVersion::series).collect()
+}
+
+/// Parse a version such as `2.11.5` into its numeric parts, padded to major, minor and patch.
+pub fn parse_version(version: &str) -> Vec<u32> {
+    let mut parts: Vec<u32> = version
+        .trim()
+        .split('.')
+        .map(|part| {
+            part.chars()
+                .take_while(char::is_ascii_digit)
+                .collect::<String>()
+                .parse()
+                .unwrap_or(0)
+        })
+        .collect();
+    parts.resize(3.max(parts.len()), 0);
+    parts
+}
+
+fn series(ckan_version: &str) -> String {
+    let version = parse_version(ckan_version);
+    format!("{}.{}", version[0], version[1])
+}
+
+/// Print the catalog of supported CKAN versions.
+pub fn list() {
+    for (i, version) in catalog().iter().enumerate() {
+        let default_text = if i == 0 { " (default)" } else { "" };
+        println!(
+            "{}{default_text}\n  Python {} to {} (uv installs Python {} by default)\n  Solr image {} ({} schema)",
+            highlighted_text(&format!("CKAN {}", version.version)),
+            version.python.min,
+            version.python.max,
+            version.default_python().unwrap_or("-"),
+            version.solr_image,
+            version.solr_schema
+        );
+        let extension_refs = version
+            .extension_refs
+            .iter()
+            .map(|(name, git_ref)| format!("{name}@{git_ref}"))
+            .collect::<Vec<_>>();
+        println!("  Extensions {}", extension_refs.join(", "));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn catalog_is_valid_and_newest_first() {
+        let versions = catalog()
+            .iter()
+            .map(|version| parse_version(&version.version))
+            .collect::<Vec<_>>();
+        assert!(versions.windows(2).all(|pair| pair[0] > pair[1]));
+        assert_eq!(default_version().version, catalog()[0].version);
+    }
+
+    #[test]
+    fn find_prefers_the_exact_release() {
+        assert_eq!(find("2.11.5").unwrap().version, "2.11.5");
+        assert_eq!(find("2.10.10").unwrap().version, "2.10.10");
+    }
+
+    #[test]
+    fn find_falls_back_to_the_series() {
+        assert_eq!(find("2.11.4").unwrap().version, "2.11.5");
+        assert_eq!(find("2.10.1").unwrap().version, "2.10.10");
+    }
+
+    #[test]
+    fn find_rejects_unsupported_series() {
+        assert!(find("2.9.11").is_none());
+        assert!(find("2.1.10").is_none());
+        assert!(find("3.0.0").is_none());
+    }
+
+    #[test]
+    fn supports_python_is_inclusive() {
+        let version = find("2.10.10").unwrap();
+        assert!(version.supports_python(&version.python.min));
+        assert!(version.supports_python(&version.python.max));
+        assert!(!version.supports_python("3.12"));
+        assert_eq!(version.default_python(), Some("3.10"));
+    }
+
+    #[test]
+    fn parse_version_pads_to_three_parts() {
+        assert_eq!(parse_version("2.11.5"), vec![2, 11, 5]);
+        assert_eq!(parse_version("2.11"), vec![2, 11, 0]);
+        assert_eq!(parse_version("3"), vec![3, 0, 0]);
+    }
+
+    #[test]
+    fn parse_version_ignores_suffixes_and_whitespace() {
+        assert_eq!(parse_version("2.11.0b1\n"), vec![2, 11, 0]);
+        assert_eq!(parse_version(" 4.0.0 "), vec![4, 0, 0]);
+        assert_eq!(parse_version("1.2.3.4"), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn parse_version_orders_numerically() {
+        assert!(parse_version("2.10.10") > parse_version("2.10.9"));
+        assert!(parse_version("2.11.0") > parse_version("2.10.10"));
+        assert_eq!(parse_version("2.11"), parse_version("2.11.0"));
+    }
+}